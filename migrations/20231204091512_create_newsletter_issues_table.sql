@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE newsletter_issues(
+    newsletter_issue_id uuid NOT NULL,
+    PRIMARY KEY (newsletter_issue_id),
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    published_at timestamptz NOT NULL
+);
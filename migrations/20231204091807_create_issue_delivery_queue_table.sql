@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE issue_delivery_queue(
+    newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    delivered_at timestamptz,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
@@ -0,0 +1,14 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues RENAME COLUMN content TO html_content;
+ALTER TABLE newsletter_issues ADD COLUMN text_content TEXT;
+-- Existing issues only have HTML: keep line breaks, drop the tags and decode the common entities.
+UPDATE newsletter_issues SET text_content = btrim(
+    replace(replace(replace(replace(replace(
+        regexp_replace(
+            regexp_replace(html_content, '<br\s*/?>|</(p|div|li|h[1-6])>', E'\n', 'gi'),
+            '<[^>]*>', '', 'g'
+        ),
+        '&nbsp;', ' '), '&lt;', '<'), '&gt;', '>'), '&quot;', '"'), '&amp;', '&'),
+    E' \n'
+);
+ALTER TABLE newsletter_issues ALTER COLUMN text_content SET NOT NULL;
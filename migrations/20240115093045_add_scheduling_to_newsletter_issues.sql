@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NOT NULL DEFAULT 'published';
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_status_check
+    CHECK (status IN ('scheduled', 'published', 'cancelled'));
+ALTER TABLE newsletter_issues ADD COLUMN send_at timestamptz;
+ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+CREATE INDEX newsletter_issues_due_idx ON newsletter_issues (send_at) WHERE status = 'scheduled';
@@ -0,0 +1,20 @@
+-- Add migration script here
+CREATE TABLE lists(
+    list_id uuid NOT NULL,
+    PRIMARY KEY (list_id),
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+-- Everything so far went to the one implicit list.
+INSERT INTO lists (list_id, slug, name) VALUES (gen_random_uuid(), 'default', 'Newsletter');
+
+ALTER TABLE subscriptions ADD COLUMN list_id uuid REFERENCES lists (list_id);
+UPDATE subscriptions SET list_id = (SELECT list_id FROM lists WHERE slug = 'default');
+ALTER TABLE subscriptions ALTER COLUMN list_id SET NOT NULL;
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_list_id_email_key UNIQUE (list_id, email);
+
+ALTER TABLE newsletter_issues ADD COLUMN list_id uuid REFERENCES lists (list_id);
+UPDATE newsletter_issues SET list_id = (SELECT list_id FROM lists WHERE slug = 'default');
+ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
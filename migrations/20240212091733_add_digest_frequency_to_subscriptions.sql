@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN digest_frequency TEXT NOT NULL DEFAULT 'immediate'
+    CHECK (digest_frequency IN ('immediate', 'daily', 'weekly'));
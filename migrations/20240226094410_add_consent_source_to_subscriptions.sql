@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN consent_source TEXT NOT NULL DEFAULT 'signup_form';
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE confirmation_email_queue(
+    subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id) ON DELETE CASCADE,
+    subscription_token TEXT NOT NULL,
+    n_attempts INTEGER NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    last_error TEXT,
+    PRIMARY KEY (subscriber_id)
+);
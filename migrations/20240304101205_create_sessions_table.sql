@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE sessions(
+    session_key TEXT NOT NULL,
+    PRIMARY KEY (session_key),
+    state JSONB NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN is_superuser BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN disabled_at timestamptz;
+-- Every existing admin could manage everything so far.
+UPDATE users SET is_superuser = true;
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_user_id_fkey,
+    ADD CONSTRAINT idempotency_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE;
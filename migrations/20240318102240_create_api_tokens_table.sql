@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE api_tokens(
+    token_id uuid NOT NULL,
+    PRIMARY KEY (token_id),
+    user_id uuid NOT NULL
+        REFERENCES users (user_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz,
+    last_used_at timestamptz
+);
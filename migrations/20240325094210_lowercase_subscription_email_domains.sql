@@ -0,0 +1,20 @@
+-- Add migration script here
+-- Addresses are stored with their domain lowercased, so the (list_id, email) constraint
+-- catches the same address typed in another case. Of several spellings of one address on
+-- a list only the oldest is rewritten, the others are left as they were.
+UPDATE subscriptions s
+SET email = n.email
+FROM (
+    SELECT DISTINCT ON (list_id, email) id, email
+    FROM (
+        SELECT id, list_id, subscribed_at,
+               substring(email from '^(.*)@') || '@' || lower(substring(email from '@([^@]*)$')) AS email
+        FROM subscriptions
+    ) lowercased
+    ORDER BY list_id, email, subscribed_at
+) n
+WHERE s.id = n.id
+    AND s.email <> n.email
+    AND NOT EXISTS (
+        SELECT 1 FROM subscriptions o WHERE o.list_id = s.list_id AND o.email = n.email
+    );
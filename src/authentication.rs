@@ -0,0 +1,270 @@
+use actix_web::http::header;
+use actix_web::http::header::HeaderValue;
+use actix_web::HttpResponseBuilder;
+use actix_web::{http::header::HeaderMap, HttpResponse};
+use anyhow::Context;
+use argon2::password_hash::{PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, PasswordHash, Version};
+use base64::{engine::general_purpose, Engine as _};
+use secrecy::ExposeSecret;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use crate::domain::{AdminPassword, ApiScope, ApiToken};
+
+pub fn get_unauthorized_response() -> HttpResponseBuilder {
+    let mut response = HttpResponse::Unauthorized();
+    let header_value = HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
+    response.insert_header((header::WWW_AUTHENTICATE, header_value));
+    response
+}
+
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
+        .to_string();
+    Ok(Credentials {
+        username,
+        password: Secret::new(password),
+    })
+}
+/// `None` when the request doesn't use the 'Bearer' scheme at all.
+pub fn bearer_token(headers: &HeaderMap) -> Option<Result<ApiToken, anyhow::Error>> {
+    let header_value = headers.get("Authorization")?.to_str().ok()?;
+    // Auth schemes are case-insensitive (RFC 9110).
+    let (scheme, token) = header_value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return None;
+    }
+    Some(ApiToken::parse(token.trim().to_owned()).map_err(|e| anyhow::anyhow!(e)))
+}
+
+/// Basic credentials of an admin, or a Bearer API token granted `scope`.
+pub async fn authenticate(
+    headers: &HeaderMap,
+    pool: &PgPool,
+    scope: ApiScope,
+) -> Result<uuid::Uuid, HttpResponse> {
+    if let Some(token) = bearer_token(headers) {
+        let token = token.map_err(|e| {
+            tracing::error!("Failed to authorize: {}", e);
+            get_unauthorized_response().finish()
+        })?;
+        return validate_api_token(token, scope, pool).await;
+    }
+    let credentials = basic_authentication(headers).map_err(|e| {
+        tracing::error!("Failed to authorize: {}", e);
+        get_unauthorized_response().finish()
+    })?;
+    // Lands on the handler's span, e.g. `publish_newsletter` declares a `username` field.
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    validate_credentials(credentials, pool)
+        .await
+        .map_err(|mut http_response| {
+            tracing::error!("Failed to validate credentials");
+            http_response.finish()
+        })
+}
+
+#[tracing::instrument(name = "Validate API token", skip(token, pool))]
+pub async fn validate_api_token(
+    token: ApiToken,
+    scope: ApiScope,
+    pool: &PgPool,
+) -> Result<uuid::Uuid, HttpResponse> {
+    // Expired tokens and tokens of disabled admins are as good as unknown.
+    let row = sqlx::query!(
+        r#"
+            SELECT t.token_id, t.user_id, t.scopes
+            FROM api_tokens t
+            JOIN users u ON u.user_id = t.user_id
+            WHERE t.token_hash = $1
+                AND u.disabled_at IS NULL
+                AND (t.expires_at IS NULL OR t.expires_at > now())
+        "#,
+        token.hash(),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+    let row = row.ok_or_else(|| {
+        tracing::error!("Unknown or expired API token");
+        get_unauthorized_response().finish()
+    })?;
+    if !row.scopes.iter().any(|s| s == scope.as_str()) {
+        return Err(HttpResponse::Forbidden()
+            .body(format!("The API token lacks the {} scope", scope.as_str())));
+    }
+    // Only a request the token is actually allowed to make counts as a use.
+    sqlx::query!(
+        "UPDATE api_tokens SET last_used_at = now() WHERE token_id = $1",
+        row.token_id,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+    Ok(row.user_id)
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pool: &PgPool,
+) -> Result<uuid::Uuid, HttpResponseBuilder> {
+    let mut expected_password_hash = Secret::new(
+        "$argon2id$v=19$m=15000,t=2,p=1$\
+        gZiV/M1gPc22ElAH/Jh1Hw$\
+        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
+            .to_string(),
+    );
+    let mut user_id = None;
+    if let Some((stored_user_id, strored_expected_password_hash)) =
+        get_stored_credentials(&credentials.username, pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to get stored credentials: {}", e);
+                HttpResponse::InternalServerError()
+            })?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = strored_expected_password_hash;
+    }
+    tokio::task::spawn_blocking(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to start blocking task: {}", e);
+        HttpResponse::InternalServerError()
+    })?
+    .map_err(|e| {
+        tracing::error!("Failed to validate password: {}", e);
+        get_unauthorized_response()
+    })?;
+    user_id.ok_or_else(get_unauthorized_response)
+}
+#[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
+async fn get_stored_credentials(
+    username: &str,
+    pool: &PgPool,
+) -> Result<Option<(uuid::Uuid, Secret<String>)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT user_id, password_hash
+            FROM users
+            WHERE username = $1 AND disabled_at IS NULL
+        "#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve stored credentials.")?
+    .map(|row| (row.user_id, Secret::new(row.password_hash)));
+    Ok(row)
+}
+
+#[tracing::instrument(
+    name = "Verify password hash",
+    skip(expected_password_hash, password_candidate)
+)]
+pub fn verify_password_hash(
+    expected_password_hash: Secret<String>,
+    password_candidate: Secret<String>,
+) -> Result<(), argon2::password_hash::Error> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())?;
+    Argon2::default().verify_password(
+        password_candidate.expose_secret().as_bytes(),
+        &expected_password_hash,
+    )
+}
+
+#[tracing::instrument(name = "Get username", skip(pool))]
+pub async fn get_username(
+    user_id: uuid::Uuid,
+    pool: &PgPool,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT username
+            FROM users
+            WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a username.")?;
+    Ok(row.map(|row| row.username))
+}
+
+/// Hashes on a blocking thread, argon2 is slow on purpose and would stall the executor.
+pub async fn hash_password(password: AdminPassword) -> Result<Secret<String>, anyhow::Error> {
+    tokio::task::spawn_blocking(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task.")?
+}
+
+fn compute_password_hash(password: AdminPassword) -> Result<Secret<String>, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    // The same parameters as the fallback hash in `validate_credentials`.
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).unwrap(),
+    )
+    .hash_password(password.expose_secret().as_bytes(), &salt)
+    .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?
+    .to_string();
+    Ok(Secret::new(password_hash))
+}
+
+#[tracing::instrument(name = "Change password", skip(password, pool))]
+pub async fn change_password(
+    user_id: uuid::Uuid,
+    password: AdminPassword,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let password_hash = hash_password(password).await?;
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET password_hash = $1
+            WHERE user_id = $2
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to change user's password in the database.")?;
+    Ok(())
+}
@@ -1,5 +1,12 @@
 use std::net::IpAddr;
+use std::time::Duration;
 
+use anyhow::Context;
+use lettre::message::dkim::{
+    DkimCanonicalization, DkimCanonicalizationType, DkimConfig, DkimSigningAlgorithm,
+    DkimSigningKey,
+};
+use lettre::message::header::HeaderName;
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::{postgres::PgConnectOptions, ConnectOptions};
@@ -9,7 +16,12 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
-    pub smtp_sever: SMTPSettings,
+}
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        self.application.validate()?;
+        self.email_client.validate()
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -18,6 +30,24 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: IpAddr,
     pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    /// Signs the session and flash message cookies, so it can be rotated without
+    /// invalidating the tokens signed with `hmac_secret`.
+    pub session_key: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub subscription_token_ttl_hours: i64,
+}
+impl ApplicationSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        let session_key = self.session_key.expose_secret();
+        if session_key.len() < 64 {
+            return Err("The session_key must be at least 64 bytes long".to_owned());
+        }
+        if session_key == self.hmac_secret.expose_secret() {
+            return Err("The session_key must differ from the hmac_secret".to_owned());
+        }
+        Ok(())
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -46,7 +76,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host.to_string())
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
@@ -66,14 +96,208 @@ impl DatabaseSettings {
 pub struct EmailClientSettings {
     pub user_name: String,
     pub user_mail: String,
-    pub password: Secret<String>,
-    pub test_sever: bool,
+    pub transport: EmailTransportSettings,
+    pub dkim: Option<DkimSettings>,
+    /// Holds one directory of confirmation templates per locale.
+    pub templates_directory: String,
+    pub default_locale: String,
+}
+impl EmailClientSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        self.user_mail
+            .parse::<lettre::Address>()
+            .map_err(|e| format!("Invalid sender mail {}: {}", self.user_mail, e))?;
+        if let Some(dkim) = &self.dkim {
+            dkim.validate()?;
+        }
+        match &self.transport {
+            EmailTransportSettings::Smtp(smtp) => smtp.validate(),
+            EmailTransportSettings::HttpApi(http_api) => http_api.validate(),
+            EmailTransportSettings::InMemory | EmailTransportSettings::Maildir { .. } => Ok(()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EmailTransportSettings {
+    /// Any SMTP relay, e.g. our Postfix, Gmail or the embedded test server.
+    Smtp(SMTPSettings),
+    /// A transactional provider's REST API, e.g. Postmark or SES.
+    HttpApi(HttpApiSettings),
+    /// Records messages in memory without sending them.
+    InMemory,
+    /// Writes each message into a maildir rooted at `directory`.
+    Maildir { directory: String },
 }
 
 #[derive(serde::Deserialize)]
 pub struct SMTPSettings {
-    pub smtp_port: u16,
-    pub smtp_host: IpAddr,
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SMTPTlsMode,
+    #[serde(default)]
+    pub auth: SMTPAuthSettings,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_secs: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub pool_max_size: u32,
+}
+impl SMTPSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if self.host.trim().is_empty() || self.host.contains(char::is_whitespace) {
+            return Err(format!("Invalid SMTP host {:?}", self.host));
+        }
+        if self.port == 0 {
+            return Err("SMTP port must not be 0".to_owned());
+        }
+        if self.timeout_secs == 0 {
+            return Err("SMTP timeout must be at least one second".to_owned());
+        }
+        if self.pool_max_size == 0 {
+            return Err("SMTP pool must allow at least one connection".to_owned());
+        }
+        if let SMTPAuthSettings::Plain { username, .. } | SMTPAuthSettings::Login { username, .. } =
+            &self.auth
+        {
+            if username.is_empty() {
+                return Err("SMTP username must not be empty".to_owned());
+            }
+            if let SMTPTlsMode::None = self.tls {
+                return Err(
+                    "Refusing to send SMTP credentials over an unencrypted connection".to_owned(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct HttpApiSettings {
+    /// Messages are POSTed to `{base_url}/email`, keep a trailing slash on any path prefix.
+    pub base_url: String,
+    pub auth_token: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+impl HttpApiSettings {
+    pub fn base_url(&self) -> Result<reqwest::Url, String> {
+        let base_url = reqwest::Url::parse(&self.base_url)
+            .map_err(|e| format!("Invalid email API base url {}: {}", self.base_url, e))?;
+        match base_url.scheme() {
+            "http" | "https" => Ok(base_url),
+            scheme => Err(format!(
+                "Email API base url must be http(s), found {}",
+                scheme
+            )),
+        }
+    }
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_milliseconds)
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        self.base_url()?;
+        if self.auth_token.expose_secret().is_empty() {
+            return Err("Email API auth token must not be empty".to_owned());
+        }
+        if self.timeout_milliseconds == 0 {
+            return Err("Email API timeout must not be 0".to_owned());
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct DkimSettings {
+    pub selector: String,
+    pub domain: String,
+    pub algorithm: DkimAlgorithm,
+    /// A PKCS#1 PEM file for RSA, or the base64 encoded 32 byte key for Ed25519.
+    pub private_key_file: String,
+}
+impl DkimSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.selector.trim().is_empty() || self.domain.trim().is_empty() {
+            return Err("DKIM selector and domain must not be empty".to_owned());
+        }
+        Ok(())
+    }
+    pub fn signing_config(&self) -> Result<DkimConfig, anyhow::Error> {
+        let private_key = Secret::new(
+            std::fs::read_to_string(&self.private_key_file)
+                .with_context(|| format!("Failed to read DKIM key {}", self.private_key_file))?,
+        );
+        let algorithm = match self.algorithm {
+            DkimAlgorithm::Rsa => DkimSigningAlgorithm::Rsa,
+            DkimAlgorithm::Ed25519 => DkimSigningAlgorithm::Ed25519,
+        };
+        let private_key = DkimSigningKey::new(private_key.expose_secret().trim(), algorithm)
+            .map_err(|e| anyhow::anyhow!("Invalid DKIM key: {:?}", e))?;
+        // Relaxed canonicalization survives relays that refold headers or trim whitespace.
+        // Content-Type is left out: lettre writes a multipart's one after signing.
+        let relaxed = DkimCanonicalization {
+            header: DkimCanonicalizationType::Relaxed,
+            body: DkimCanonicalizationType::Relaxed,
+        };
+        let headers = [
+            "From",
+            "To",
+            "Subject",
+            "Date",
+            "Message-ID",
+            "MIME-Version",
+            "List-Unsubscribe",
+            "List-Unsubscribe-Post",
+        ]
+        .into_iter()
+        .map(HeaderName::new_from_ascii_str)
+        .collect();
+        Ok(DkimConfig::new(
+            self.selector.clone(),
+            self.domain.clone(),
+            private_key,
+            headers,
+            relaxed,
+        ))
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DkimAlgorithm {
+    Rsa,
+    Ed25519,
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SMTPTlsMode {
+    /// Plaintext for the whole session, only meant for local relays.
+    None,
+    /// Upgrade a plaintext connection with STARTTLS, usually on port 587.
+    Starttls,
+    /// TLS from the first byte, usually on port 465.
+    Implicit,
+}
+
+#[derive(serde::Deserialize, Default)]
+#[serde(tag = "mechanism", rename_all = "snake_case")]
+pub enum SMTPAuthSettings {
+    #[default]
+    None,
+    Plain {
+        username: String,
+        password: Secret<String>,
+    },
+    Login {
+        username: String,
+        password: Secret<String>,
+    },
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
@@ -95,7 +319,13 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
                 .separator("__"),
         )
         .build()
-        .and_then(|x| x.try_deserialize())
+        .and_then(|x| x.try_deserialize::<Settings>())
+        .and_then(|settings| {
+            settings
+                .validate()
+                .map_err(config::ConfigError::Message)?;
+            Ok(settings)
+        })
 }
 
 pub enum Enviroment {
@@ -120,3 +350,90 @@ impl TryFrom<String> for Enviroment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ApplicationSettings, HttpApiSettings, SMTPAuthSettings, SMTPSettings, SMTPTlsMode,
+    };
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    fn relay() -> SMTPSettings {
+        SMTPSettings {
+            host: "relay.example.com".to_owned(),
+            port: 587,
+            tls: SMTPTlsMode::Starttls,
+            auth: SMTPAuthSettings::Plain {
+                username: "newsletter".to_owned(),
+                password: Secret::new("password".to_owned()),
+            },
+            timeout_secs: 10,
+            pool_max_size: 4,
+        }
+    }
+
+    fn application() -> ApplicationSettings {
+        ApplicationSettings {
+            port: 8000,
+            host: "127.0.0.1".parse().unwrap(),
+            base_url: "http://127.0.0.1".to_owned(),
+            hmac_secret: Secret::new("h".repeat(64)),
+            session_key: Secret::new("s".repeat(64)),
+            subscription_token_ttl_hours: 48,
+        }
+    }
+
+    #[test]
+    fn session_key_must_be_long_and_distinct_from_hmac_secret() {
+        let mut settings = application();
+        assert_ok!(settings.validate());
+        settings.session_key = Secret::new("s".repeat(63));
+        assert_err!(settings.validate());
+        settings.session_key = settings.hmac_secret.clone();
+        assert_err!(settings.validate());
+    }
+    #[test]
+    fn authenticated_starttls_relay_is_valid() {
+        assert_ok!(relay().validate());
+    }
+    #[test]
+    fn hostname_with_whitespace_is_rejected() {
+        let mut settings = relay();
+        settings.host = "relay example.com".to_owned();
+        assert_err!(settings.validate());
+    }
+    #[test]
+    fn zero_port_timeout_or_pool_size_is_rejected() {
+        let mut settings = relay();
+        settings.port = 0;
+        assert_err!(settings.validate());
+        let mut settings = relay();
+        settings.timeout_secs = 0;
+        assert_err!(settings.validate());
+        let mut settings = relay();
+        settings.pool_max_size = 0;
+        assert_err!(settings.validate());
+    }
+    #[test]
+    fn credentials_over_plaintext_are_rejected() {
+        let mut settings = relay();
+        settings.tls = SMTPTlsMode::None;
+        assert_err!(settings.validate());
+        settings.auth = SMTPAuthSettings::None;
+        assert_ok!(settings.validate());
+    }
+    #[test]
+    fn email_api_base_url_must_be_http() {
+        let mut settings = HttpApiSettings {
+            base_url: "https://api.example.com/".to_owned(),
+            auth_token: Secret::new("token".to_owned()),
+            timeout_milliseconds: 10_000,
+        };
+        assert_ok!(settings.validate());
+        settings.base_url = "ftp://api.example.com/".to_owned();
+        assert_err!(settings.validate());
+        settings.base_url = "api.example.com".to_owned();
+        assert_err!(settings.validate());
+    }
+}
@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use lettre::Address;
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{PreferencesToken, Subscriber, SubscriberName, SubscriptionStatus},
+    email_client::{ConfirmationTemplates, EmailClient},
+    issue_delivery_worker::{get_retry_delay, ExecutionOutcome, MAX_ATTEMPTS},
+};
+
+struct Task {
+    subscriber_id: Uuid,
+    subscription_token: String,
+    n_attempts: i32,
+    email: String,
+    name: String,
+    status: String,
+    locale: Option<String>,
+}
+impl TryInto<Subscriber> for Task {
+    type Error = String;
+    fn try_into(self) -> Result<Subscriber, Self::Error> {
+        let email = self.email.parse::<Address>().map_err(|x| format!("{x}"))?;
+        let name = SubscriberName::parse(self.name)?;
+        Ok(Subscriber { email, name })
+    }
+}
+
+pub async fn run_confirmation_worker_until_stopped(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    confirmation_templates: ConfirmationTemplates,
+) -> Result<(), std::io::Error> {
+    loop {
+        match try_send_confirmation(
+            &pool,
+            &email_client,
+            &base_url,
+            &hmac_secret,
+            &confirmation_templates,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Sends one queued confirmation email in the subscriber's stored locale. The subscriber
+/// and their token are already committed, so a failed send only ever retries the email.
+#[tracing::instrument(
+    name = "Send a queued confirmation email",
+    skip_all,
+    fields(subscriber_id=tracing::field::Empty),
+    err
+)]
+pub async fn try_send_confirmation(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    confirmation_templates: &ConfirmationTemplates,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    let subscriber_id = task.subscriber_id;
+    tracing::Span::current().record("subscriber_id", tracing::field::display(subscriber_id));
+
+    if task.status != SubscriptionStatus::PendingConfirmation.as_str() {
+        tracing::info!("Skipping a subscriber who is no longer pending confirmation");
+        delete_task(&mut transaction, subscriber_id).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit confirmation task")?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let n_attempts = task.n_attempts + 1;
+    let subscription_token = task.subscription_token.clone();
+    let locale = confirmation_templates
+        .negotiate_locale(task.locale.as_deref(), None)
+        .to_owned();
+    let subscriber: Subscriber = match task.try_into() {
+        Ok(subscriber) => subscriber,
+        Err(e) => {
+            tracing::error!("Skipping a subscriber with invalid stored details: {}", e);
+            delete_task(&mut transaction, subscriber_id).await?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit confirmation task")?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    let preferences_token = PreferencesToken::generate(subscriber_id, hmac_secret);
+    let preferences_link = EmailClient::get_preferences_link(base_url, preferences_token.as_ref());
+    let outcome = email_client
+        .send_confirmation(
+            &subscriber,
+            base_url,
+            &subscription_token,
+            &preferences_link,
+            confirmation_templates,
+            &locale,
+        )
+        .await;
+    match outcome {
+        Ok(_) => delete_task(&mut transaction, subscriber_id).await?,
+        Err(e) if e.is_permanent() || n_attempts >= MAX_ATTEMPTS => {
+            // The subscriber stays pending, signing up again mails them a fresh link.
+            tracing::error!(
+                "Giving up on a confirmation email after {} attempts: {}",
+                n_attempts,
+                e
+            );
+            delete_task(&mut transaction, subscriber_id).await?;
+        }
+        Err(e) => {
+            let retry_delay = get_retry_delay(n_attempts);
+            tracing::warn!(
+                "Confirmation attempt {} failed, retrying in {:?}: {}",
+                n_attempts,
+                retry_delay,
+                e
+            );
+            reschedule_task(
+                &mut transaction,
+                subscriber_id,
+                retry_delay,
+                &e.to_string(),
+            )
+            .await?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit confirmation task")?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[tracing::instrument(
+    name = "Enqueue a confirmation email",
+    skip(transaction, subscription_token)
+)]
+pub async fn enqueue_confirmation(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO confirmation_email_queue (subscriber_id, subscription_token)
+            VALUES ($1, $2)
+        "#,
+        subscriber_id,
+        subscription_token,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Dequeue a confirmation task", skip(pool))]
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(Transaction<'static, Postgres>, Task)>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let task = sqlx::query_as!(
+        Task,
+        r#"
+            SELECT q.subscriber_id, q.subscription_token, q.n_attempts,
+                   s.email, s.name, s.status, s.locale
+            FROM confirmation_email_queue q
+            JOIN subscriptions s ON s.id = q.subscriber_id
+            WHERE q.execute_after <= now()
+            LIMIT 1
+            FOR UPDATE OF q SKIP LOCKED
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to fetch a pending confirmation task")?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(name = "Delete confirmation task", skip(transaction))]
+async fn delete_task(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM confirmation_email_queue WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete confirmation task")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Reschedule confirmation task", skip(transaction, last_error))]
+async fn reschedule_task(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    retry_delay: Duration,
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
+    let execute_after = chrono::Utc::now() + chrono::Duration::from_std(retry_delay)?;
+    sqlx::query!(
+        r#"
+            UPDATE confirmation_email_queue
+            SET n_attempts = n_attempts + 1, execute_after = $2, last_error = $3
+            WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+        execute_after,
+        last_error,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to reschedule confirmation task")?;
+    Ok(())
+}
@@ -0,0 +1,286 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use lettre::Address;
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{
+        IssueTemplate, PreferencesToken, Subscriber, SubscriberName, SubscriptionStatus,
+        TemplateContext, TemplateError, TemplateFormat, UnsubscribeToken,
+    },
+    email_client::{EmailClient, PreferencesLink, UnsubscribeLink},
+    issue_delivery_worker::{
+        append_footer, mark_tasks_dead_letter, mark_tasks_skipped, record_send_outcome,
+        render_issue, ExecutionOutcome, NewsletterIssue,
+    },
+    routes::html_escape,
+};
+
+/// A due `digest` task, with the subscription and issue it is for. Digest frequency is
+/// set per address, so one digest gathers the tasks of every list the address is on.
+struct DigestEntry {
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    n_attempts: i32,
+    email: String,
+    name: String,
+    status: String,
+    title: String,
+    html_content: String,
+    text_content: String,
+}
+impl TryInto<Subscriber> for &DigestEntry {
+    type Error = String;
+    fn try_into(self) -> Result<Subscriber, Self::Error> {
+        let email = self.email.parse::<Address>().map_err(|x| format!("{x}"))?;
+        let name = SubscriberName::parse(self.name.clone())?;
+        Ok(Subscriber { email, name })
+    }
+}
+
+pub async fn run_digest_worker_until_stopped(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+) -> Result<(), std::io::Error> {
+    loop {
+        match try_send_digest(&pool, &email_client, &base_url, &hmac_secret).await {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Sends an address every `digest` task that has come due, across all its lists, as a
+/// single email. The tasks share a schedule, so they succeed, retry or dead-letter together.
+#[tracing::instrument(
+    name = "Send a digest",
+    skip_all,
+    fields(subscriber_id=tracing::field::Empty, n_issues=tracing::field::Empty),
+    err
+)]
+pub async fn try_send_digest(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, entries)) = dequeue_digest(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    let (entries, left): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|entry| entry.status == SubscriptionStatus::Confirmed.as_str());
+    if !left.is_empty() {
+        tracing::info!("Skipping digest issues of lists the subscriber has left");
+        let (newsletter_issue_ids, subscriber_ids) = task_ids(&left);
+        mark_tasks_skipped(&mut transaction, &newsletter_issue_ids, &subscriber_ids).await?;
+    }
+    // The unsubscribe link leaves the list of the oldest issue, the preferences link
+    // reaches every list.
+    let Some(first) = entries.first() else {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit digest")?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    };
+    let subscriber_id = first.subscriber_id;
+    tracing::Span::current()
+        .record("subscriber_id", tracing::field::display(subscriber_id))
+        .record("n_issues", entries.len());
+    let (newsletter_issue_ids, subscriber_ids) = task_ids(&entries);
+    let n_attempts = entries
+        .iter()
+        .map(|entry| entry.n_attempts)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let subscriber: Subscriber = match first.try_into() {
+        Ok(subscriber) => subscriber,
+        Err(e) => {
+            tracing::error!("Skipping a subscriber with invalid stored details: {}", e);
+            mark_tasks_dead_letter(&mut transaction, &newsletter_issue_ids, &subscriber_ids, &e)
+                .await?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit digest")?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    let unsubscribe_token = UnsubscribeToken::generate(subscriber_id, hmac_secret);
+    let unsubscribe_link = EmailClient::get_unsubscribe_link(base_url, unsubscribe_token.as_ref());
+    let preferences_token = PreferencesToken::generate(subscriber_id, hmac_secret);
+    let preferences_link = EmailClient::get_preferences_link(base_url, preferences_token.as_ref());
+    let (subject, html_content, text_content) = match render_digest(
+        &entries,
+        &subscriber,
+        base_url,
+        &unsubscribe_link,
+        &preferences_link,
+    ) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            tracing::error!("Failed to render digest: {}", e);
+            mark_tasks_dead_letter(
+                &mut transaction,
+                &newsletter_issue_ids,
+                &subscriber_ids,
+                &e.to_string(),
+            )
+            .await?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit digest")?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    let outcome = email_client
+        .send_newsletter(
+            subscriber.name.as_ref().to_owned(),
+            subscriber.email,
+            &subject,
+            &html_content,
+            &text_content,
+            &unsubscribe_link,
+        )
+        .await;
+    record_send_outcome(
+        &mut transaction,
+        &newsletter_issue_ids,
+        &subscriber_ids,
+        outcome,
+        n_attempts,
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit digest")?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+fn task_ids(entries: &[DigestEntry]) -> (Vec<Uuid>, Vec<Uuid>) {
+    entries
+        .iter()
+        .map(|entry| (entry.newsletter_issue_id, entry.subscriber_id))
+        .unzip()
+}
+
+/// A digest holding a single issue goes out as that issue, anything more is stitched
+/// together oldest first under one footer.
+fn render_digest(
+    entries: &[DigestEntry],
+    subscriber: &Subscriber,
+    base_url: &str,
+    unsubscribe_link: &UnsubscribeLink,
+    preferences_link: &PreferencesLink,
+) -> Result<(String, String, String), TemplateError> {
+    if let [entry] = entries {
+        let issue = NewsletterIssue {
+            title: entry.title.clone(),
+            html_content: entry.html_content.clone(),
+            text_content: entry.text_content.clone(),
+        };
+        let archive_link = EmailClient::get_archive_link(base_url, entry.newsletter_issue_id);
+        let (html_content, text_content) = render_issue(
+            &issue,
+            subscriber,
+            unsubscribe_link,
+            preferences_link,
+            &archive_link,
+        )?;
+        return Ok((issue.title, html_content, text_content));
+    }
+    let mut html_sections = Vec::with_capacity(entries.len());
+    let mut text_sections = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let archive_link = EmailClient::get_archive_link(base_url, entry.newsletter_issue_id);
+        let context = TemplateContext::new(subscriber, &unsubscribe_link.0, &archive_link.0);
+        let html_content =
+            IssueTemplate::parse(entry.html_content.to_owned(), TemplateFormat::Html)?
+                .render(&context)?;
+        let text_content =
+            IssueTemplate::parse(entry.text_content.to_owned(), TemplateFormat::Text)?
+                .render(&context)?;
+        html_sections.push(format!(
+            "<h2><a href=\"{}\">{}</a></h2>{}",
+            archive_link.0,
+            html_escape(&entry.title),
+            html_content
+        ));
+        text_sections.push(format!(
+            "{}\n{}\n\n{}",
+            entry.title, archive_link.0, text_content
+        ));
+    }
+    let subject = format!("Your newsletter digest: {} new issues", entries.len());
+    let (html_content, text_content) = append_footer(
+        html_sections.join("<hr>"),
+        text_sections.join("\n\n---\n\n"),
+        unsubscribe_link,
+        preferences_link,
+    );
+    Ok((subject, html_content, text_content))
+}
+
+type DueDigest = (Transaction<'static, Postgres>, Vec<DigestEntry>);
+
+/// Every due `digest` task of one address. An empty digest means another worker sent it
+/// while this one waited for the address.
+#[tracing::instrument(name = "Dequeue a digest", skip(pool))]
+async fn dequeue_digest(
+    pool: &PgPool,
+) -> Result<Option<DueDigest>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let email = sqlx::query_scalar!(
+        r#"
+            SELECT s.email
+            FROM issue_delivery_queue q
+            JOIN subscriptions s ON s.id = q.subscriber_id
+            WHERE q.status = 'digest' AND q.execute_after <= now()
+            LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to fetch a due digest")?;
+    let Some(email) = email else {
+        return Ok(None);
+    };
+    // The address spans several subscription rows, so workers take turns on the address
+    // itself rather than skip-locking rows and each sending half of the digest.
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(&email)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to lock the address of a due digest")?;
+    let entries = sqlx::query_as!(
+        DigestEntry,
+        r#"
+            SELECT q.newsletter_issue_id, q.subscriber_id, q.n_attempts,
+                   s.email, s.name, s.status, i.title, i.html_content, i.text_content
+            FROM issue_delivery_queue q
+            JOIN subscriptions s ON s.id = q.subscriber_id
+            JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+            WHERE s.email = $1 AND q.status = 'digest' AND q.execute_after <= now()
+            ORDER BY i.published_at, i.newsletter_issue_id
+            FOR UPDATE OF q
+        "#,
+        email,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to fetch the issues of a due digest")?;
+    Ok(Some((transaction, entries)))
+}
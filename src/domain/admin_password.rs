@@ -0,0 +1,67 @@
+use secrecy::{ExposeSecret, Secret};
+
+/// A new admin password that passed the strength rules, the only kind we hash and store.
+#[derive(Debug)]
+pub struct AdminPassword(Secret<String>);
+
+impl AdminPassword {
+    pub fn parse(s: Secret<String>) -> Result<AdminPassword, String> {
+        let password = s.expose_secret();
+        let length = password.chars().count();
+        if length < 12 {
+            return Err("The password must be at least 12 characters long".into());
+        }
+        if length > 128 {
+            return Err("The password must be at most 128 characters long".into());
+        }
+        let has_letter = password.chars().any(char::is_alphabetic);
+        let has_other = password.chars().any(|c| !c.is_alphabetic());
+        if !has_letter || !has_other {
+            return Err("The password must mix letters with digits or symbols".into());
+        }
+        Ok(Self(s))
+    }
+}
+
+impl ExposeSecret<String> for AdminPassword {
+    fn expose_secret(&self) -> &String {
+        self.0.expose_secret()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::AdminPassword;
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+    #[test]
+    fn a_12_character_password_is_valid() {
+        let password = Secret::new("abcdefghij12".to_string());
+        assert_ok!(AdminPassword::parse(password));
+    }
+    #[test]
+    fn a_password_shorter_than_12_characters_is_rejected() {
+        let password = Secret::new("abcdefghi12".to_string());
+        assert_err!(AdminPassword::parse(password));
+    }
+    #[test]
+    fn a_password_longer_than_128_characters_is_rejected() {
+        let password = Secret::new(format!("{}1", "a".repeat(128)));
+        assert_err!(AdminPassword::parse(password));
+    }
+    #[test]
+    fn letters_only_passwords_are_rejected() {
+        let password = Secret::new("abcdefghijklmnop".to_string());
+        assert_err!(AdminPassword::parse(password));
+    }
+    #[test]
+    fn passwords_without_letters_are_rejected() {
+        let password = Secret::new("1234567890123456".to_string());
+        assert_err!(AdminPassword::parse(password));
+    }
+    #[test]
+    fn a_uuid_is_a_valid_password() {
+        let password = Secret::new(uuid::Uuid::new_v4().to_string());
+        assert_ok!(AdminPassword::parse(password));
+    }
+}
@@ -0,0 +1,44 @@
+/// What an API token may be used for, each scope unlocks a handful of endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiScope {
+    NewsletterPublish,
+    SubscribersRead,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::NewsletterPublish => "newsletter:publish",
+            ApiScope::SubscribersRead => "subscribers:read",
+        }
+    }
+}
+
+impl TryFrom<String> for ApiScope {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "newsletter:publish" => Ok(Self::NewsletterPublish),
+            "subscribers:read" => Ok(Self::SubscribersRead),
+            other => Err(format!("{} Not a valid API scope", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ApiScope::{self, *};
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn scopes_round_trip_through_their_string_form() {
+        for scope in [NewsletterPublish, SubscribersRead] {
+            assert_ok_eq!(ApiScope::try_from(scope.as_str().to_string()), scope);
+        }
+    }
+    #[test]
+    fn unknown_scopes_are_rejected() {
+        assert_err!(ApiScope::try_from("subscribers:write".to_string()));
+        assert_err!(ApiScope::try_from("".to_string()));
+    }
+}
@@ -0,0 +1,76 @@
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+const PREFIX: &str = "nlt_";
+
+/// The secret half of an API token, only ever shown to its owner once.
+#[derive(Debug)]
+pub struct ApiToken(Secret<String>);
+
+impl ApiToken {
+    pub fn generate() -> ApiToken {
+        let mut rng = thread_rng();
+        let secret: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(40)
+            .collect();
+        Self(Secret::new(format!("{}{}", PREFIX, secret)))
+    }
+    pub fn parse(s: String) -> Result<ApiToken, String> {
+        let is_well_formed = s.strip_prefix(PREFIX).is_some_and(|secret| {
+            secret.len() == 40 && secret.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+        if !is_well_formed {
+            return Err("Not a valid API token".into());
+        }
+        Ok(Self(Secret::new(s)))
+    }
+    /// What we store and look tokens up by. A fast hash is enough, the token is 238 random bits.
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.0.expose_secret().as_bytes()))
+    }
+}
+
+impl ExposeSecret<String> for ApiToken {
+    fn expose_secret(&self) -> &String {
+        self.0.expose_secret()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ApiToken;
+    use claim::{assert_err, assert_ok};
+    use secrecy::ExposeSecret;
+
+    #[test]
+    fn generated_tokens_parse() {
+        let token = ApiToken::generate();
+        assert_ok!(ApiToken::parse(token.expose_secret().clone()));
+    }
+    #[test]
+    fn generated_tokens_are_unique() {
+        assert_ne!(ApiToken::generate().hash(), ApiToken::generate().hash());
+    }
+    #[test]
+    fn the_hash_is_stable_and_hides_the_token() {
+        let token = ApiToken::generate();
+        let parsed = ApiToken::parse(token.expose_secret().clone()).unwrap();
+        assert_eq!(token.hash(), parsed.hash());
+        assert!(!token.hash().contains(&token.expose_secret()[4..]));
+    }
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        for token in [
+            "".to_string(),
+            "nlt_".to_string(),
+            "a".repeat(44),
+            format!("nlt_{}", "a".repeat(39)),
+            format!("nlt_{}!", "a".repeat(39)),
+        ] {
+            assert_err!(ApiToken::parse(token));
+        }
+    }
+}
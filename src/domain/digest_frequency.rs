@@ -0,0 +1,51 @@
+/// How often a subscriber wants their mail, chosen from the preference center. Daily and
+/// weekly subscribers get every issue of a UTC day or ISO week in one digest email.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestFrequency {
+    Immediate,
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Immediate => "immediate",
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        }
+    }
+}
+
+impl TryFrom<String> for DigestFrequency {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "immediate" => Ok(Self::Immediate),
+            "daily" => Ok(Self::Daily),
+            "weekly" => Ok(Self::Weekly),
+            other => Err(format!("{} Not a valid digest frequency", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::DigestFrequency::{self, *};
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn frequencies_round_trip_through_their_string_form() {
+        for frequency in [Immediate, Daily, Weekly] {
+            assert_ok_eq!(
+                DigestFrequency::try_from(frequency.as_str().to_owned()),
+                frequency
+            );
+        }
+    }
+    #[test]
+    fn unknown_frequencies_are_rejected() {
+        assert_err!(DigestFrequency::try_from("hourly".to_owned()));
+    }
+}
@@ -0,0 +1,195 @@
+use minijinja::{AutoEscape, Environment, UndefinedBehavior, Value};
+
+use crate::domain::Subscriber;
+
+#[derive(Debug, Clone, Copy)]
+pub enum TemplateFormat {
+    Html,
+    Text,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TemplateError {
+    pub line: Option<usize>,
+    pub message: String,
+}
+impl From<minijinja::Error> for TemplateError {
+    fn from(e: minijinja::Error) -> Self {
+        let message = match e.detail() {
+            Some(detail) => format!("{}: {}", e.kind(), detail),
+            None => e.kind().to_string(),
+        };
+        TemplateError {
+            line: e.line(),
+            message,
+        }
+    }
+}
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} (line {})", self.message, line),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberContext<'a> {
+    name: &'a str,
+    email: String,
+}
+
+/// The variables an issue body can use, e.g. `{{ subscriber.name }}` or `{{ unsubscribe_url }}`.
+#[derive(serde::Serialize)]
+pub struct TemplateContext<'a> {
+    subscriber: SubscriberContext<'a>,
+    // Links are ours, marking them safe keeps the HTML escaper from mangling their slashes.
+    unsubscribe_url: Value,
+    archive_url: Value,
+}
+impl<'a> TemplateContext<'a> {
+    pub fn new(subscriber: &'a Subscriber, unsubscribe_url: &'a str, archive_url: &'a str) -> Self {
+        Self {
+            subscriber: SubscriberContext {
+                name: subscriber.name.as_ref(),
+                email: subscriber.email.to_string(),
+            },
+            unsubscribe_url: Value::from_safe_string(unsubscribe_url.to_owned()),
+            archive_url: Value::from_safe_string(archive_url.to_owned()),
+        }
+    }
+    fn sample() -> Self {
+        Self {
+            subscriber: SubscriberContext {
+                name: "Subscriber",
+                email: "subscriber@example.com".to_owned(),
+            },
+            unsubscribe_url: Value::from_safe_string(
+                "https://example.com/subscriptions/unsubscribe".to_owned(),
+            ),
+            archive_url: Value::from_safe_string(
+                "https://example.com/newsletter/archive".to_owned(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IssueTemplate {
+    source: String,
+    format: TemplateFormat,
+}
+impl IssueTemplate {
+    /// Rejects templates that fail to compile or use variables we never provide.
+    pub fn parse(source: String, format: TemplateFormat) -> Result<IssueTemplate, TemplateError> {
+        let template = Self { source, format };
+        template.render(&TemplateContext::sample())?;
+        Ok(template)
+    }
+    pub fn render(&self, context: &TemplateContext) -> Result<String, TemplateError> {
+        self.render_with(UndefinedBehavior::Strict, context)
+    }
+    /// Renders the public copy of an issue, where no subscriber details are known.
+    pub fn render_for_archive(&self, archive_url: &str) -> Result<String, TemplateError> {
+        self.render_with(
+            UndefinedBehavior::Chainable,
+            minijinja::context! { archive_url => Value::from_safe_string(archive_url.to_owned()) },
+        )
+    }
+    fn render_with<S: serde::Serialize>(
+        &self,
+        undefined_behavior: UndefinedBehavior,
+        context: S,
+    ) -> Result<String, TemplateError> {
+        let mut environment = Environment::new();
+        environment.set_undefined_behavior(undefined_behavior);
+        let auto_escape = match self.format {
+            TemplateFormat::Html => AutoEscape::Html,
+            TemplateFormat::Text => AutoEscape::None,
+        };
+        environment.set_auto_escape_callback(move |_| auto_escape);
+        Ok(environment.render_str(&self.source, context)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{
+        IssueTemplate, Subscriber, SubscriberName, TemplateContext, TemplateFormat,
+    };
+    use claim::{assert_err, assert_ok};
+
+    fn subscriber(name: &str) -> Subscriber {
+        Subscriber {
+            email: "ursula@example.com".parse().unwrap(),
+            name: SubscriberName::parse(name.to_owned()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn plain_content_is_a_valid_template() {
+        assert_ok!(IssueTemplate::parse(
+            "<p>Hello</p>".to_owned(),
+            TemplateFormat::Html
+        ));
+    }
+    #[test]
+    fn variables_are_rendered_per_subscriber() {
+        let template = IssueTemplate::parse(
+            "Hi {{ subscriber.name }} ({{ subscriber.email }}), leave at {{ unsubscribe_url }}"
+                .to_owned(),
+            TemplateFormat::Text,
+        )
+        .unwrap();
+        let subscriber = subscriber("Ursula");
+        let rendered = template
+            .render(&TemplateContext::new(&subscriber, "https://u", "https://a"))
+            .unwrap();
+        assert_eq!(
+            rendered,
+            "Hi Ursula (ursula@example.com), leave at https://u"
+        );
+    }
+    #[test]
+    fn html_templates_escape_subscriber_details() {
+        let template = IssueTemplate::parse(
+            "<p>{{ subscriber.name }}</p>".to_owned(),
+            TemplateFormat::Html,
+        )
+        .unwrap();
+        let subscriber = subscriber("Tom & Jerry");
+        let rendered = template
+            .render(&TemplateContext::new(&subscriber, "https://u", "https://a"))
+            .unwrap();
+        assert_eq!(rendered, "<p>Tom &amp; Jerry</p>");
+    }
+    #[test]
+    fn syntax_errors_point_at_the_offending_line() {
+        let error = IssueTemplate::parse(
+            "<p>Hello</p>\n<p>{{ subscriber.name </p>".to_owned(),
+            TemplateFormat::Html,
+        )
+        .unwrap_err();
+        assert_eq!(error.line, Some(2));
+    }
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_err!(IssueTemplate::parse(
+            "{{ subscriber.nmae }}".to_owned(),
+            TemplateFormat::Text
+        ));
+    }
+    #[test]
+    fn archive_copies_render_without_a_subscriber() {
+        let template = IssueTemplate::parse(
+            "Hi {{ subscriber.name }}, read online at {{ archive_url }}".to_owned(),
+            TemplateFormat::Text,
+        )
+        .unwrap();
+        assert_eq!(
+            template.render_for_archive("https://a").unwrap(),
+            "Hi , read online at https://a"
+        );
+    }
+}
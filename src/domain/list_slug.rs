@@ -0,0 +1,61 @@
+/// The list subscribers sign up to when they don't name one.
+pub const DEFAULT_LIST_SLUG: &str = "default";
+
+#[derive(Debug)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_too_long = s.len() > 64;
+        let has_invalid_characters = !s
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        let has_dangling_dash = s.starts_with('-') || s.ends_with('-');
+
+        if s.is_empty() || is_too_long || has_invalid_characters || has_dangling_dash {
+            Err(format!("{} Not a valid list slug", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{ListSlug, DEFAULT_LIST_SLUG};
+    use claim::{assert_err, assert_ok};
+    #[test]
+    fn the_default_slug_is_valid() {
+        assert_ok!(ListSlug::parse(DEFAULT_LIST_SLUG.to_string()));
+    }
+    #[test]
+    fn lowercase_words_joined_by_dashes_are_valid() {
+        assert_ok!(ListSlug::parse("rust-weekly-2024".to_string()));
+    }
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+    }
+    #[test]
+    fn a_slug_longer_than_64_characters_is_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+    #[test]
+    fn slugs_with_uppercase_spaces_or_symbols_are_rejected() {
+        for slug in ["Weekly", "rust weekly", "rust_weekly", "rust/weekly"] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+    #[test]
+    fn slugs_starting_or_ending_with_a_dash_are_rejected() {
+        for slug in ["-weekly", "weekly-"] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+}
@@ -1,5 +1,29 @@
+mod admin_password;
+mod api_scope;
+mod api_token;
+mod digest_frequency;
+mod issue_template;
+mod list_slug;
+mod preferences_token;
+mod segment;
 mod subscriber;
+mod subscriber_cursor;
 mod subscriber_name;
+mod subscriber_tag;
+mod subscription_status;
+mod unsubscribe_token;
 
+pub use admin_password::*;
+pub use api_scope::*;
+pub use api_token::*;
+pub use digest_frequency::*;
+pub use issue_template::*;
+pub use list_slug::*;
+pub use preferences_token::*;
+pub use segment::*;
 pub use subscriber::*;
-pub use subscriber_name::*;
\ No newline at end of file
+pub use subscriber_cursor::*;
+pub use subscriber_name::*;
+pub use subscriber_tag::*;
+pub use subscription_status::*;
+pub use unsubscribe_token::*;
\ No newline at end of file
@@ -0,0 +1,69 @@
+use hmac::Mac;
+use secrecy::Secret;
+use uuid::Uuid;
+
+use super::unsubscribe_token::get_mac;
+
+/// Grants access to the preference center of whoever owns the subscription it was issued for.
+#[derive(Debug)]
+pub struct PreferencesToken(String);
+
+impl PreferencesToken {
+    pub fn generate(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> PreferencesToken {
+        let tag = hex::encode(
+            get_mac(b"preferences:", subscriber_id, hmac_secret)
+                .finalize()
+                .into_bytes(),
+        );
+        Self(format!("{}.{}", subscriber_id, tag))
+    }
+    /// Returns the subscriber id the token was issued for, if its signature checks out.
+    pub fn parse(s: &str, hmac_secret: &Secret<String>) -> Result<Uuid, String> {
+        let (subscriber_id, tag) = s
+            .split_once('.')
+            .ok_or_else(|| format!("{} Not a valid preferences token", s))?;
+        let subscriber_id = Uuid::parse_str(subscriber_id).map_err(|e| e.to_string())?;
+        let tag = hex::decode(tag).map_err(|e| e.to_string())?;
+        get_mac(b"preferences:", subscriber_id, hmac_secret)
+            .verify_slice(&tag)
+            .map_err(|_| format!("{} Not a valid preferences token", s))?;
+        Ok(subscriber_id)
+    }
+}
+
+impl AsRef<str> for PreferencesToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{PreferencesToken, UnsubscribeToken};
+    use claim::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("super-long-and-secret-random-key".to_string())
+    }
+    #[test]
+    fn a_generated_token_is_parsed_successfully() {
+        let subscriber_id = Uuid::new_v4();
+        let token = PreferencesToken::generate(subscriber_id, &secret());
+        assert_ok_eq!(
+            PreferencesToken::parse(token.as_ref(), &secret()),
+            subscriber_id
+        );
+    }
+    #[test]
+    fn an_unsubscribe_token_is_not_a_preferences_token() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        assert_err!(PreferencesToken::parse(token.as_ref(), &secret()));
+    }
+    #[test]
+    fn a_preferences_token_is_not_an_unsubscribe_token() {
+        let token = PreferencesToken::generate(Uuid::new_v4(), &secret());
+        assert_err!(UnsubscribeToken::parse(token.as_ref(), &secret()));
+    }
+}
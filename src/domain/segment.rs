@@ -0,0 +1,65 @@
+use chrono::{DateTime, Utc};
+
+use crate::domain::SubscriberTag;
+
+/// Which confirmed subscribers of a list an issue goes to, e.g.
+/// `{"and": [{"tag": "rust"}, {"not": {"tag": "beginner"}}, {"subscribed_after": "2024-01-01T00:00:00Z"}]}`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Segment {
+    Tag(SubscriberTag),
+    SubscribedAfter(DateTime<Utc>),
+    /// Matches everyone when empty.
+    And(Vec<Segment>),
+    /// Matches no one when empty.
+    Or(Vec<Segment>),
+    Not(Box<Segment>),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{Segment, SubscriberTag};
+    use claim::assert_err;
+
+    fn tag(tag: &str) -> Segment {
+        Segment::Tag(SubscriberTag::parse(tag.to_string()).unwrap())
+    }
+
+    #[test]
+    fn nested_expressions_are_parsed() {
+        let segment: Segment = serde_json::from_value(serde_json::json!({
+            "and": [
+                {"tag": "rust"},
+                {"or": [{"tag": "go"}, {"not": {"tag": "Beginner"}}]},
+                {"subscribed_after": "2024-01-01T00:00:00Z"},
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            segment,
+            Segment::And(vec![
+                tag("rust"),
+                Segment::Or(vec![tag("go"), Segment::Not(Box::new(tag("beginner")))]),
+                Segment::SubscribedAfter("2024-01-01T00:00:00Z".parse().unwrap()),
+            ])
+        );
+    }
+    #[test]
+    fn invalid_tags_are_rejected() {
+        assert_err!(serde_json::from_value::<Segment>(
+            serde_json::json!({"tag": "not a tag"})
+        ));
+    }
+    #[test]
+    fn unknown_operators_are_rejected() {
+        assert_err!(serde_json::from_value::<Segment>(
+            serde_json::json!({"xor": [{"tag": "rust"}]})
+        ));
+    }
+    #[test]
+    fn segments_survive_a_round_trip() {
+        let segment = Segment::Not(Box::new(Segment::And(vec![tag("rust"), tag("go")])));
+        let stored = serde_json::to_value(&segment).unwrap();
+        assert_eq!(serde_json::from_value::<Segment>(stored).unwrap(), segment);
+    }
+}
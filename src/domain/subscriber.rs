@@ -7,10 +7,34 @@ pub struct Subscriber {
     pub email: Address,
     pub name: SubscriberName,
 }
+impl Subscriber {
+    /// Parses an address the way subscriptions store it: the domain, which is case-insensitive,
+    /// lowercased and the local part as typed, so the same address always compares equal.
+    pub fn parse_email(email: &str) -> Result<Address, String> {
+        let email = email.parse::<Address>().map_err(|e| e.to_string())?;
+        Address::new(email.user(), email.domain().to_lowercase()).map_err(|e| e.to_string())
+    }
+}
 impl TryFrom<FormData> for Subscriber {
     type Error = String;
     fn try_from(form_data: FormData) -> Result<Self, Self::Error> {
         let subscriber_name = SubscriberName::parse(form_data.name.clone())?;
-        form_data.email.parse::<Address>().map(|email| Subscriber{email, name: subscriber_name}).map_err(|e| e.to_string())
+        Subscriber::parse_email(&form_data.email).map(|email| Subscriber{email, name: subscriber_name})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Subscriber;
+    use claim::assert_err;
+
+    #[test]
+    fn the_domain_is_lowercased_and_the_local_part_kept() {
+        let email = Subscriber::parse_email("Ursula.Le.Guin@GMail.COM").unwrap();
+        assert_eq!(email.to_string(), "Ursula.Le.Guin@gmail.com");
+    }
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        assert_err!(Subscriber::parse_email("ursula.gmail.com"));
     }
 }
@@ -0,0 +1,58 @@
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Where a page of subscribers ended: the `(subscribed_at, id)` of its last row.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SubscriberCursor {
+    pub subscribed_at: DateTime<Utc>,
+    pub subscriber_id: Uuid,
+}
+
+impl SubscriberCursor {
+    pub fn encode(&self) -> String {
+        general_purpose::URL_SAFE_NO_PAD.encode(format!(
+            "{}|{}",
+            self.subscribed_at.to_rfc3339(),
+            self.subscriber_id
+        ))
+    }
+    pub fn parse(s: &str) -> Result<SubscriberCursor, String> {
+        let invalid = || format!("{} Not a valid cursor", s);
+        let decoded = general_purpose::URL_SAFE_NO_PAD
+            .decode(s)
+            .map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (subscribed_at, subscriber_id) = decoded.split_once('|').ok_or_else(invalid)?;
+        Ok(Self {
+            subscribed_at: DateTime::parse_from_rfc3339(subscribed_at)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            subscriber_id: Uuid::parse_str(subscriber_id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberCursor;
+    use chrono::Utc;
+    use claim::{assert_err, assert_ok_eq};
+    use uuid::Uuid;
+
+    #[test]
+    fn an_encoded_cursor_is_parsed_back() {
+        let cursor = SubscriberCursor {
+            subscribed_at: Utc::now(),
+            subscriber_id: Uuid::new_v4(),
+        };
+        let encoded = cursor.encode();
+        assert_ok_eq!(SubscriberCursor::parse(&encoded), cursor);
+    }
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        for cursor in ["", "not base64!", "bm8tc2VwYXJhdG9y", "YWJjfGRlZg"] {
+            assert_err!(SubscriberCursor::parse(cursor));
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/// A label on a subscription, e.g. `rust` or `early-bird`, used to target segments.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    /// Tags are case-insensitive, so they are stored lowercased.
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let tag = s.trim().to_lowercase();
+        let is_too_long = tag.len() > 64;
+        let has_invalid_characters = !tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if tag.is_empty() || is_too_long || has_invalid_characters {
+            Err(format!("{} Not a valid tag", s))
+        } else {
+            Ok(Self(tag))
+        }
+    }
+}
+
+impl TryFrom<String> for SubscriberTag {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberTag;
+    use claim::{assert_err, assert_ok};
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        let tag = SubscriberTag::parse(" Early-Bird ".to_string()).unwrap();
+        assert_eq!(tag.as_ref(), "early-bird");
+    }
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(SubscriberTag::parse(" ".to_string()));
+    }
+    #[test]
+    fn a_tag_longer_than_64_characters_is_rejected() {
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+        assert_ok!(SubscriberTag::parse("a".repeat(64)));
+    }
+    #[test]
+    fn tags_with_spaces_or_symbols_are_rejected() {
+        for tag in ["rust lang", "rust,go", "c++", "ünïcode"] {
+            assert_err!(SubscriberTag::parse(tag.to_string()));
+        }
+    }
+}
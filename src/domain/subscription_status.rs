@@ -0,0 +1,107 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    Bounced,
+    Complained,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "pending_confirmation",
+            SubscriptionStatus::Confirmed => "confirmed",
+            SubscriptionStatus::Unsubscribed => "unsubscribed",
+            SubscriptionStatus::Bounced => "bounced",
+            SubscriptionStatus::Complained => "complained",
+        }
+    }
+    /// The single source of truth for how a subscription may move between states.
+    pub fn can_transition_to(&self, next: SubscriptionStatus) -> bool {
+        use SubscriptionStatus::*;
+        matches!(
+            (self, next),
+            (PendingConfirmation, Confirmed)
+                | (PendingConfirmation, Unsubscribed)
+                | (Confirmed, Unsubscribed)
+                | (Confirmed, Bounced)
+                | (Confirmed, Complained)
+                | (Unsubscribed, PendingConfirmation)
+                | (Bounced, PendingConfirmation)
+        )
+    }
+    /// Rejoining a list from the preference center, where the token mailed to the address
+    /// already proves ownership. Unlike `can_transition_to`, someone who left may go straight
+    /// back to `Confirmed`; bounces and complaints stay put.
+    pub fn can_rejoin(&self) -> bool {
+        use SubscriptionStatus::*;
+        matches!(self, PendingConfirmation | Unsubscribed)
+    }
+}
+
+impl TryFrom<String> for SubscriptionStatus {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "pending_confirmation" => Ok(Self::PendingConfirmation),
+            "confirmed" => Ok(Self::Confirmed),
+            "unsubscribed" => Ok(Self::Unsubscribed),
+            "bounced" => Ok(Self::Bounced),
+            "complained" => Ok(Self::Complained),
+            other => Err(format!("{} Not a valid subscription status", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriptionStatus::{self, *};
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn statuses_round_trip_through_their_string_form() {
+        for status in [
+            PendingConfirmation,
+            Confirmed,
+            Unsubscribed,
+            Bounced,
+            Complained,
+        ] {
+            assert_ok_eq!(
+                SubscriptionStatus::try_from(status.as_str().to_string()),
+                status
+            );
+        }
+    }
+    #[test]
+    fn unknown_status_is_rejected() {
+        assert_err!(SubscriptionStatus::try_from("active".to_string()));
+    }
+    #[test]
+    fn only_pending_subscribers_can_be_confirmed() {
+        assert!(PendingConfirmation.can_transition_to(Confirmed));
+        for status in [Confirmed, Unsubscribed, Bounced, Complained] {
+            assert!(!status.can_transition_to(Confirmed));
+        }
+    }
+    #[test]
+    fn complained_subscribers_can_never_be_mailed_again() {
+        for status in [PendingConfirmation, Confirmed, Unsubscribed, Bounced] {
+            assert!(!Complained.can_transition_to(status));
+        }
+    }
+    #[test]
+    fn unsubscribed_subscribers_can_subscribe_again() {
+        assert!(Unsubscribed.can_transition_to(PendingConfirmation));
+        assert!(!Unsubscribed.can_transition_to(Confirmed));
+    }
+    #[test]
+    fn only_pending_and_unsubscribed_subscribers_can_rejoin() {
+        assert!(PendingConfirmation.can_rejoin());
+        assert!(Unsubscribed.can_rejoin());
+        for status in [Confirmed, Bounced, Complained] {
+            assert!(!status.can_rejoin());
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn generate(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> UnsubscribeToken {
+        let tag = hex::encode(
+            get_mac(b"unsubscribe:", subscriber_id, hmac_secret)
+                .finalize()
+                .into_bytes(),
+        );
+        Self(format!("{}.{}", subscriber_id, tag))
+    }
+    /// Returns the subscriber id the token was issued for, if its signature checks out.
+    pub fn parse(s: &str, hmac_secret: &Secret<String>) -> Result<Uuid, String> {
+        let (subscriber_id, tag) = s
+            .split_once('.')
+            .ok_or_else(|| format!("{} Not a valid unsubscribe token", s))?;
+        let subscriber_id = Uuid::parse_str(subscriber_id).map_err(|e| e.to_string())?;
+        let tag = hex::decode(tag).map_err(|e| e.to_string())?;
+        get_mac(b"unsubscribe:", subscriber_id, hmac_secret)
+            .verify_slice(&tag)
+            .map_err(|_| format!("{} Not a valid unsubscribe token", s))?;
+        Ok(subscriber_id)
+    }
+}
+
+/// `purpose` keeps a token signed for one use from being accepted for another.
+pub(super) fn get_mac(
+    purpose: &[u8],
+    subscriber_id: Uuid,
+    hmac_secret: &Secret<String>,
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC can take key of any size");
+    mac.update(purpose);
+    mac.update(subscriber_id.as_bytes());
+    mac
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::UnsubscribeToken;
+    use claim::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("super-long-and-secret-random-key".to_string())
+    }
+    #[test]
+    fn a_generated_token_is_parsed_successfully() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &secret());
+        assert_ok_eq!(
+            UnsubscribeToken::parse(token.as_ref(), &secret()),
+            subscriber_id
+        );
+    }
+    #[test]
+    fn a_token_for_another_subscriber_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        let (_, tag) = token.as_ref().split_once('.').unwrap();
+        let forged = format!("{}.{}", Uuid::new_v4(), tag);
+        assert_err!(UnsubscribeToken::parse(&forged, &secret()));
+    }
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &Secret::new("other".to_string()));
+        assert_err!(UnsubscribeToken::parse(token.as_ref(), &secret()));
+    }
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        for token in ["", "no-separator", "not-a-uuid.abcd", "."] {
+            assert_err!(UnsubscribeToken::parse(token, &secret()));
+        }
+    }
+}
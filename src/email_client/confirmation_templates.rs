@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use minijinja::{AutoEscape, Environment, UndefinedBehavior, Value};
+
+use super::{ConfirmationLink, PreferencesLink};
+use crate::domain::{Subscriber, TemplateError};
+
+#[derive(Clone)]
+struct ConfirmationTemplate {
+    subject: String,
+    html: String,
+    text: String,
+}
+
+pub struct ConfirmationEmail {
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+/// Confirmation email templates, one `{locale}/subject.txt`, `body.html` and `body.txt`
+/// set per locale directory, all of them seeing `subscriber`, `confirmation_url` and `preferences_url`.
+#[derive(Clone)]
+pub struct ConfirmationTemplates {
+    default_locale: String,
+    locales: HashMap<String, ConfirmationTemplate>,
+}
+impl ConfirmationTemplates {
+    pub fn load(directory: impl AsRef<Path>, default_locale: &str) -> Result<Self, anyhow::Error> {
+        let directory = directory.as_ref();
+        let mut locales = HashMap::new();
+        let entries = std::fs::read_dir(directory)
+            .with_context(|| format!("Failed to read confirmation templates in {:?}", directory))?;
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let locale = entry.file_name().to_string_lossy().to_lowercase();
+            let read = |file: &str| {
+                std::fs::read_to_string(entry.path().join(file))
+                    .with_context(|| format!("Failed to read {} for locale {}", file, locale))
+            };
+            let template = ConfirmationTemplate {
+                subject: read("subject.txt")?,
+                html: read("body.html")?,
+                text: read("body.txt")?,
+            };
+            template
+                .render(&sample_context())
+                .map_err(|e| anyhow::anyhow!("Invalid {} confirmation template: {}", locale, e))?;
+            locales.insert(locale, template);
+        }
+        let default_locale = default_locale.to_lowercase();
+        if !locales.contains_key(&default_locale) {
+            anyhow::bail!(
+                "No confirmation templates for the default locale {}",
+                default_locale
+            );
+        }
+        Ok(Self {
+            default_locale,
+            locales,
+        })
+    }
+    /// Picks the explicitly requested locale if we have it, then the best Accept-Language
+    /// match, then the default.
+    pub fn negotiate_locale(&self, requested: Option<&str>, accept_language: Option<&str>) -> &str {
+        let candidates = requested.into_iter().map(str::to_owned).chain(
+            accept_language
+                .map(parse_accept_language)
+                .unwrap_or_default(),
+        );
+        for candidate in candidates {
+            let candidate = candidate.to_lowercase();
+            let primary = candidate.split('-').next().unwrap_or_default();
+            for locale in [candidate.as_str(), primary] {
+                if let Some((locale, _)) = self.locales.get_key_value(locale) {
+                    return locale;
+                }
+            }
+        }
+        &self.default_locale
+    }
+    pub fn render(
+        &self,
+        locale: &str,
+        subscriber: &Subscriber,
+        confirmation_link: &ConfirmationLink,
+        preferences_link: &PreferencesLink,
+    ) -> Result<ConfirmationEmail, TemplateError> {
+        let template = self
+            .locales
+            .get(locale)
+            .unwrap_or_else(|| &self.locales[&self.default_locale]);
+        template.render(&minijinja::context! {
+            subscriber => minijinja::context! {
+                name => subscriber.name.as_ref(),
+                email => subscriber.email.to_string(),
+            },
+            confirmation_url => Value::from_safe_string(confirmation_link.0.clone()),
+            preferences_url => Value::from_safe_string(preferences_link.0.clone()),
+        })
+    }
+}
+
+impl ConfirmationTemplate {
+    fn render(&self, context: &Value) -> Result<ConfirmationEmail, TemplateError> {
+        let render = |source: &str, auto_escape: AutoEscape| {
+            let mut environment = Environment::new();
+            environment.set_undefined_behavior(UndefinedBehavior::Strict);
+            environment.set_auto_escape_callback(move |_| auto_escape);
+            environment.render_str(source, context)
+        };
+        Ok(ConfirmationEmail {
+            subject: render(&self.subject, AutoEscape::None)?.trim().to_owned(),
+            html_content: render(&self.html, AutoEscape::Html)?,
+            text_content: render(&self.text, AutoEscape::None)?,
+        })
+    }
+}
+
+fn sample_context() -> Value {
+    minijinja::context! {
+        subscriber => minijinja::context! {
+            name => "Subscriber",
+            email => "subscriber@example.com",
+        },
+        confirmation_url => Value::from_safe_string("https://example.com/subscriptions/confirm".to_owned()),
+        preferences_url => Value::from_safe_string("https://example.com/subscriptions/preferences".to_owned()),
+    }
+}
+
+/// Language tags ordered by their quality value, e.g. `fr-CH, fr;q=0.9, en;q=0.8`.
+fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.trim().split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            if tag.is_empty() || tag == "*" || quality <= 0.0 {
+                return None;
+            }
+            Some((tag.to_owned(), quality))
+        })
+        .collect();
+    // A stable sort keeps the header's order between equally weighted languages.
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages.into_iter().map(|(tag, _)| tag).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_accept_language, ConfirmationTemplates};
+    use crate::domain::{Subscriber, SubscriberName};
+    use crate::email_client::{ConfirmationLink, PreferencesLink};
+
+    fn templates() -> ConfirmationTemplates {
+        ConfirmationTemplates::load("templates/confirmation", "en").unwrap()
+    }
+
+    #[test]
+    fn accept_language_is_ordered_by_quality() {
+        assert_eq!(
+            parse_accept_language("en;q=0.8, fr-CH, de;q=0.9, *;q=0.5, it;q=0"),
+            vec!["fr-CH", "de", "en"]
+        );
+    }
+    #[test]
+    fn requested_locale_wins_over_accept_language() {
+        assert_eq!(templates().negotiate_locale(Some("fr"), Some("en")), "fr");
+    }
+    #[test]
+    fn regional_variants_fall_back_to_their_language() {
+        assert_eq!(
+            templates().negotiate_locale(None, Some("fr-CA, en;q=0.5")),
+            "fr"
+        );
+    }
+    #[test]
+    fn unsupported_locales_fall_back_to_the_default() {
+        assert_eq!(
+            templates().negotiate_locale(Some("xx"), Some("de, ja")),
+            "en"
+        );
+        assert_eq!(templates().negotiate_locale(None, None), "en");
+    }
+    #[test]
+    fn missing_default_locale_is_rejected() {
+        assert!(ConfirmationTemplates::load("templates/confirmation", "de").is_err());
+    }
+    #[test]
+    fn confirmation_is_rendered_in_the_chosen_locale() {
+        let subscriber = Subscriber {
+            email: "ursula@example.com".parse().unwrap(),
+            name: SubscriberName::parse("Ursula".to_owned()).unwrap(),
+        };
+        let link = ConfirmationLink("https://example.com/confirm?token=abc".to_owned());
+        let preferences = PreferencesLink("https://example.com/preferences?token=def".to_owned());
+        let email = templates()
+            .render("fr", &subscriber, &link, &preferences)
+            .unwrap();
+        assert!(email
+            .html_content
+            .contains("href=\"https://example.com/confirm?token=abc\""));
+        assert!(email
+            .text_content
+            .contains("https://example.com/preferences?token=def"));
+        assert!(email.text_content.contains("Ursula"));
+        assert!(!email.subject.contains('\n'));
+    }
+}
@@ -0,0 +1,188 @@
+use base64::{engine::general_purpose, Engine};
+use lettre::Message;
+use reqwest::{StatusCode, Url};
+use secrecy::{ExposeSecret, Secret};
+
+use super::{EmailTransport, SendEmailError};
+use crate::configuration::HttpApiSettings;
+
+/// Hands the raw MIME message to a transactional provider's REST API, so every header we
+/// set (List-Unsubscribe, multipart bodies) survives exactly as it would over SMTP.
+pub struct HttpApiTransport {
+    http_client: reqwest::Client,
+    base_url: Url,
+    auth_token: Secret<String>,
+}
+
+#[derive(serde::Serialize)]
+struct SendEmailRequest<'a> {
+    from: Option<String>,
+    to: Vec<String>,
+    raw_message: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+struct ProviderError {
+    message: String,
+}
+
+impl HttpApiTransport {
+    pub fn from_settings(settings: &HttpApiSettings) -> Result<Self, anyhow::Error> {
+        let http_client = reqwest::Client::builder()
+            .timeout(settings.timeout())
+            .build()?;
+        Ok(Self {
+            http_client,
+            base_url: settings.base_url().map_err(anyhow::Error::msg)?,
+            auth_token: settings.auth_token.clone(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for HttpApiTransport {
+    async fn send(&self, message: Message) -> Result<(), SendEmailError> {
+        let url = self
+            .base_url
+            .join("email")
+            .map_err(|e| SendEmailError::Permanent(e.to_string()))?;
+        let envelope = message.envelope();
+        let raw_message = general_purpose::STANDARD.encode(message.formatted());
+        let request_body = SendEmailRequest {
+            from: envelope.from().map(|from| from.to_string()),
+            to: envelope.to().iter().map(|to| to.to_string()).collect(),
+            raw_message: &raw_message,
+        };
+        let response = self
+            .http_client
+            .post(url)
+            .bearer_auth(self.auth_token.expose_secret())
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| SendEmailError::Transient(e.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let reason = match response.json::<ProviderError>().await {
+            Ok(body) => format!("{}: {}", status, body.message),
+            Err(_) => status.to_string(),
+        };
+        // Like SMTP 4xx vs 5xx: only throttling and server side trouble are worth retrying.
+        if status.is_server_error()
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::REQUEST_TIMEOUT
+        {
+            Err(SendEmailError::Transient(reason))
+        } else {
+            Err(SendEmailError::Permanent(reason))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpApiTransport;
+    use crate::configuration::HttpApiSettings;
+    use crate::email_client::EmailTransport;
+    use claim::{assert_err, assert_ok};
+    use lettre::Message;
+    use secrecy::Secret;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            match result {
+                Ok(body) => {
+                    body.get("from").is_some()
+                        && body.get("to").is_some()
+                        && body.get("raw_message").is_some()
+                }
+                Err(_) => false,
+            }
+        }
+    }
+
+    fn transport(base_url: String) -> HttpApiTransport {
+        HttpApiTransport::from_settings(&HttpApiSettings {
+            base_url,
+            auth_token: Secret::new("token".to_owned()),
+            timeout_milliseconds: 200,
+        })
+        .unwrap()
+    }
+    fn message() -> Message {
+        Message::builder()
+            .from("sender@example.com".parse().unwrap())
+            .to("recipient@example.com".parse().unwrap())
+            .subject("Hello")
+            .body("Hello".to_owned())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_fires_a_request_to_base_url() {
+        let mock_server = MockServer::start().await;
+        Mock::given(header_exists("Authorization"))
+            .and(header("Authorization", "Bearer token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok!(transport(mock_server.uri()).send(message()).await);
+    }
+    #[tokio::test]
+    async fn rejected_messages_are_permanent_failures() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(422)
+                    .set_body_json(serde_json::json!({"message": "Invalid recipient"})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = transport(mock_server.uri()).send(message()).await;
+        assert_err!(&outcome);
+        let e = outcome.unwrap_err();
+        assert!(e.is_permanent());
+        assert!(e.to_string().contains("Invalid recipient"));
+    }
+    #[tokio::test]
+    async fn server_errors_and_throttling_are_transient_failures() {
+        for status in [500, 429] {
+            let mock_server = MockServer::start().await;
+            Mock::given(any())
+                .respond_with(ResponseTemplate::new(status))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+
+            let outcome = transport(mock_server.uri()).send(message()).await;
+            assert!(!outcome.unwrap_err().is_permanent());
+        }
+    }
+    #[tokio::test]
+    async fn send_times_out_if_the_provider_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(1)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = transport(mock_server.uri()).send(message()).await;
+        assert!(!outcome.unwrap_err().is_permanent());
+    }
+}
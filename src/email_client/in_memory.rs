@@ -0,0 +1,54 @@
+use std::sync::{Arc, RwLock};
+
+use lettre::Message;
+
+use super::{EmailTransport, SendEmailError};
+
+/// Keeps every message it is given instead of delivering it, cloning shares the same record.
+#[derive(Clone, Default)]
+pub struct InMemoryTransport {
+    messages: Arc<RwLock<Vec<Message>>>,
+}
+impl InMemoryTransport {
+    pub fn messages(&self) -> Vec<Message> {
+        self.messages
+            .read()
+            .expect("Cannot read from in-memory transport")
+            .clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for InMemoryTransport {
+    async fn send(&self, message: Message) -> Result<(), SendEmailError> {
+        tracing::info!(
+            "Recorded an email to {:?} instead of sending it",
+            message.envelope().to()
+        );
+        self.messages
+            .write()
+            .map_err(|e| SendEmailError::Transient(e.to_string()))?
+            .push(message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InMemoryTransport;
+    use crate::email_client::EmailTransport;
+    use lettre::Message;
+
+    #[tokio::test]
+    async fn sent_messages_are_recorded_across_clones() {
+        let transport = InMemoryTransport::default();
+        let message = Message::builder()
+            .from("sender@example.com".parse().unwrap())
+            .to("recipient@example.com".parse().unwrap())
+            .subject("Hello")
+            .body("Hello".to_owned())
+            .unwrap();
+        transport.clone().send(message).await.unwrap();
+        assert_eq!(transport.messages().len(), 1);
+    }
+}
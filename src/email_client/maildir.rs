@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use lettre::Message;
+use uuid::Uuid;
+
+use super::{EmailTransport, SendEmailError};
+
+/// Drops every message into a maildir so local mail clients can open what would have been sent.
+pub struct MaildirTransport {
+    directory: PathBuf,
+}
+impl MaildirTransport {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for MaildirTransport {
+    async fn send(&self, message: Message) -> Result<(), SendEmailError> {
+        let tmp = self.directory.join("tmp");
+        let new = self.directory.join("new");
+        for directory in [&tmp, &new, &self.directory.join("cur")] {
+            tokio::fs::create_dir_all(directory)
+                .await
+                .map_err(|e| SendEmailError::Transient(e.to_string()))?;
+        }
+        // Maildir readers only look at `new`, so the rename makes a fully written file appear at once.
+        let file_name = format!(
+            "{}.{}.newsletter",
+            chrono::Utc::now().timestamp(),
+            Uuid::new_v4()
+        );
+        tokio::fs::write(tmp.join(&file_name), message.formatted())
+            .await
+            .map_err(|e| SendEmailError::Transient(e.to_string()))?;
+        tokio::fs::rename(tmp.join(&file_name), new.join(&file_name))
+            .await
+            .map_err(|e| SendEmailError::Transient(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MaildirTransport;
+    use crate::email_client::EmailTransport;
+    use lettre::Message;
+
+    #[tokio::test]
+    async fn messages_are_delivered_into_new() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let transport = MaildirTransport::new(&directory);
+        let message = Message::builder()
+            .from("sender@example.com".parse().unwrap())
+            .to("recipient@example.com".parse().unwrap())
+            .subject("Hello")
+            .body("Hello".to_owned())
+            .unwrap();
+        transport.send(message).await.unwrap();
+
+        let delivered: Vec<_> = std::fs::read_dir(directory.join("new")).unwrap().collect();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(std::fs::read_dir(directory.join("tmp")).unwrap().count(), 0);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+mod confirmation_templates;
+mod http;
+mod in_memory;
+mod maildir;
+mod smtp;
+mod transport;
+pub use confirmation_templates::*;
+pub use http::*;
+pub use in_memory::*;
+pub use maildir::*;
+pub use smtp::*;
+pub use transport::*;
+
+use std::sync::Arc;
+
+use lettre::{
+    message::{
+        dkim::DkimConfig,
+        header::{ContentType, Header, HeaderName, HeaderValue},
+        Mailbox, MessageBuilder, MultiPart,
+    },
+    Address, Message,
+};
+
+use uuid::Uuid;
+
+use crate::domain::Subscriber;
+
+#[derive(Clone)]
+pub struct EmailClient {
+    user_mailbox: Mailbox,
+    transport: Arc<dyn EmailTransport>,
+    dkim: Option<Arc<DkimConfig>>,
+}
+pub struct ConfirmationLink(pub String);
+pub struct UnsubscribeLink(pub String);
+pub struct PreferencesLink(pub String);
+pub struct ArchiveLink(pub String);
+
+#[derive(Clone)]
+struct ListUnsubscribe(String);
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_owned()))
+    }
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+#[derive(Clone)]
+struct ListUnsubscribePost;
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".to_owned())
+    }
+}
+
+impl EmailClient {
+    pub fn new(username: &str, user_mail: &str, transport: Arc<dyn EmailTransport>) -> Self {
+        let user_mailbox: Mailbox = Mailbox::new(
+            Some(username.to_owned()),
+            user_mail
+                .parse::<Address>()
+                .expect("Failed to parse user mail"),
+        );
+        EmailClient {
+            transport,
+            user_mailbox,
+            dkim: None,
+        }
+    }
+    /// Signs every outgoing message, whichever transport ends up carrying it.
+    pub fn with_dkim(mut self, dkim: DkimConfig) -> Self {
+        self.dkim = Some(Arc::new(dkim));
+        self
+    }
+    fn message_builder(
+        &self,
+        recipent_name: String,
+        recipent_mail: Address,
+        subject: &str,
+    ) -> MessageBuilder {
+        Message::builder()
+            .from(self.user_mailbox.clone())
+            .to(Mailbox::new(Some(recipent_name), recipent_mail))
+            .subject(subject)
+    }
+    async fn send(&self, mut email: Message) -> Result<(), SendEmailError> {
+        if let Some(dkim) = &self.dkim {
+            email.sign(dkim);
+        }
+        match self.transport.send(email).await {
+            Ok(e) => Ok(e),
+            Err(e) => {
+                tracing::error!("Failed to send email: {}", e);
+                Err(e)
+            }
+        }
+    }
+    pub async fn send_email(
+        &self,
+        recipent_name: String,
+        recipent_mail: Address,
+        subject: &str,
+        text_content: &str,
+    ) -> Result<(), SendEmailError> {
+        let email = self
+            .message_builder(recipent_name, recipent_mail, subject)
+            .header(ContentType::TEXT_HTML)
+            .body(text_content.to_owned())
+            .expect("Failed to create email");
+        self.send(email).await
+    }
+    /// Sends a list message carrying the RFC 8058 one-click unsubscribe headers.
+    pub async fn send_newsletter(
+        &self,
+        recipent_name: String,
+        recipent_mail: Address,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_link: &UnsubscribeLink,
+    ) -> Result<(), SendEmailError> {
+        let list_unsubscribe = format!(
+            "<{}>, <mailto:{}?subject=unsubscribe>",
+            unsubscribe_link.0, self.user_mailbox.email
+        );
+        let email = self
+            .message_builder(recipent_name, recipent_mail, subject)
+            .header(ListUnsubscribe(list_unsubscribe))
+            .header(ListUnsubscribePost)
+            .multipart(MultiPart::alternative_plain_html(
+                text_content.to_owned(),
+                html_content.to_owned(),
+            ))
+            .expect("Failed to create email");
+        self.send(email).await
+    }
+    pub fn get_confirmation_link(base_url: &str, subscription_token: &str) -> ConfirmationLink {
+        ConfirmationLink(format!(
+            "{}/subscriptions/confirm?subscription_token={}",
+            base_url, subscription_token
+        ))
+    }
+    pub fn get_unsubscribe_link(base_url: &str, unsubscribe_token: &str) -> UnsubscribeLink {
+        UnsubscribeLink(format!(
+            "{}/subscriptions/unsubscribe?token={}",
+            base_url, unsubscribe_token
+        ))
+    }
+    pub fn get_preferences_link(base_url: &str, preferences_token: &str) -> PreferencesLink {
+        PreferencesLink(format!(
+            "{}/subscriptions/preferences?token={}",
+            base_url, preferences_token
+        ))
+    }
+    pub fn get_archive_link(base_url: &str, newsletter_issue_id: Uuid) -> ArchiveLink {
+        ArchiveLink(format!(
+            "{}/newsletter/archive/{}",
+            base_url, newsletter_issue_id
+        ))
+    }
+    pub async fn send_confirmation(
+        &self,
+        subscriber: &Subscriber,
+        base_url: &str,
+        subscription_token: &str,
+        preferences_link: &PreferencesLink,
+        templates: &ConfirmationTemplates,
+        locale: &str,
+    ) -> Result<(), SendEmailError> {
+        let confimation_link = EmailClient::get_confirmation_link(base_url, subscription_token);
+        let confirmation = templates
+            .render(locale, subscriber, &confimation_link, preferences_link)
+            .map_err(|e| {
+                tracing::error!("Failed to render confimation: {}", e);
+                SendEmailError::Permanent(e.to_string())
+            })?;
+        let email = self
+            .message_builder(
+                subscriber.name.as_ref().to_owned(),
+                subscriber.email.clone(),
+                &confirmation.subject,
+            )
+            .multipart(MultiPart::alternative_plain_html(
+                confirmation.text_content,
+                confirmation.html_content,
+            ))
+            .expect("Failed to create email");
+
+        match self.send(email).await {
+            Ok(e) => Ok(e),
+            Err(e) => {
+                tracing::error!("Failed to send confimation: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
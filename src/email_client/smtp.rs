@@ -0,0 +1,112 @@
+use lettre::{
+    transport::smtp::{
+        authentication::{Credentials, Mechanism},
+        PoolConfig,
+    },
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use secrecy::ExposeSecret;
+
+use super::{EmailTransport, SendEmailError};
+use crate::configuration::{SMTPAuthSettings, SMTPSettings, SMTPTlsMode};
+
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+impl SmtpTransport {
+    pub fn new(mailer: AsyncSmtpTransport<Tokio1Executor>) -> Self {
+        Self { mailer }
+    }
+    pub fn from_settings(settings: &SMTPSettings) -> Result<Self, lettre::transport::smtp::Error> {
+        let builder = match settings.tls {
+            SMTPTlsMode::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            }
+            SMTPTlsMode::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+            }
+            SMTPTlsMode::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+        };
+        let builder = builder
+            .port(settings.port)
+            .timeout(Some(settings.timeout()))
+            .pool_config(PoolConfig::new().max_size(settings.pool_max_size));
+        let builder = match &settings.auth {
+            SMTPAuthSettings::None => builder,
+            SMTPAuthSettings::Plain { username, password } => builder
+                .credentials(Credentials::new(
+                    username.to_owned(),
+                    password.expose_secret().to_owned(),
+                ))
+                .authentication(vec![Mechanism::Plain]),
+            SMTPAuthSettings::Login { username, password } => builder
+                .credentials(Credentials::new(
+                    username.to_owned(),
+                    password.expose_secret().to_owned(),
+                ))
+                .authentication(vec![Mechanism::Login]),
+        };
+        Ok(Self::new(builder.build()))
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, message: Message) -> Result<(), SendEmailError> {
+        self.mailer.send(message).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmtpTransport;
+    use crate::email_client::EmailTransport;
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{AsyncSmtpTransport, Message, Tokio1Executor};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Greets and answers EHLO without advertising any AUTH mechanism.
+    async fn spawn_server_without_auth() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let reply: &[u8] = if line.starts_with("QUIT") {
+                        b"221 bye\r\n"
+                    } else {
+                        b"250 localhost\r\n"
+                    };
+                    if writer.write_all(reply).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn a_missing_auth_mechanism_is_a_transient_failure() {
+        let port = spawn_server_without_auth().await;
+        let mailer = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1")
+            .port(port)
+            .credentials(Credentials::new("user".into(), "password".into()))
+            .build();
+        let transport = SmtpTransport::new(mailer);
+        let message = Message::builder()
+            .from("sender@example.com".parse().unwrap())
+            .to("recipient@example.com".parse().unwrap())
+            .subject("Subject")
+            .body("Body".to_string())
+            .unwrap();
+
+        let outcome = transport.send(message).await;
+        assert!(!outcome.unwrap_err().is_permanent());
+    }
+}
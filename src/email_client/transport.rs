@@ -0,0 +1,43 @@
+use std::fmt;
+
+use lettre::Message;
+
+/// Where `EmailClient` hands its fully built messages off for delivery.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, message: Message) -> Result<(), SendEmailError>;
+}
+
+#[derive(Debug)]
+pub enum SendEmailError {
+    /// The message was refused outright, retrying it will not change the outcome.
+    Permanent(String),
+    /// The transport might accept the message if it is tried again later.
+    Transient(String),
+}
+impl SendEmailError {
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, SendEmailError::Permanent(_))
+    }
+}
+impl fmt::Display for SendEmailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendEmailError::Permanent(e) => write!(f, "Permanent delivery failure: {}", e),
+            SendEmailError::Transient(e) => write!(f, "Transient delivery failure: {}", e),
+        }
+    }
+}
+impl std::error::Error for SendEmailError {}
+
+impl From<lettre::transport::smtp::Error> for SendEmailError {
+    /// Only 5xx replies are final. Client errors cover relay and TLS setup, e.g. a failed
+    /// STARTTLS handshake or no usable AUTH mechanism, which a fixed config or a retry can clear.
+    fn from(e: lettre::transport::smtp::Error) -> Self {
+        if e.is_permanent() {
+            SendEmailError::Permanent(e.to_string())
+        } else {
+            SendEmailError::Transient(e.to_string())
+        }
+    }
+}
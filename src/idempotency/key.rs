@@ -0,0 +1,47 @@
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    pub fn parse(s: String) -> Result<IdempotencyKey, String> {
+        let is_empty_or_whitespace = s.trim().is_empty();
+        let is_too_long = s.chars().count() > 50;
+
+        if is_empty_or_whitespace || is_too_long {
+            Err(format!("{} Not a valid idempotency key", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::idempotency::IdempotencyKey;
+    use claim::{assert_err, assert_ok};
+    #[test]
+    fn a_50_character_long_key_is_valid() {
+        let key = "a".repeat(50);
+        assert_ok!(IdempotencyKey::parse(key));
+    }
+    #[test]
+    fn a_key_longer_than_50_characters_is_rejected() {
+        let key = "a".repeat(51);
+        assert_err!(IdempotencyKey::parse(key));
+    }
+    #[test]
+    fn empty_string_is_rejected() {
+        let key = "".to_string();
+        assert_err!(IdempotencyKey::parse(key));
+    }
+    #[test]
+    fn whitespace_only_keys_are_rejected() {
+        let key = "   ".to_string();
+        assert_err!(IdempotencyKey::parse(key));
+    }
+}
@@ -0,0 +1,431 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use lettre::Address;
+use rand::Rng;
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{
+        IssueTemplate, PreferencesToken, Subscriber, SubscriberName, SubscriptionStatus,
+        TemplateContext, TemplateError, TemplateFormat, UnsubscribeToken,
+    },
+    email_client::{ArchiveLink, EmailClient, PreferencesLink, SendEmailError, UnsubscribeLink},
+};
+
+/// Deliveries that keep failing transiently are dead-lettered after this many attempts.
+pub(crate) const MAX_ATTEMPTS: i32 = 8;
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    n_attempts: i32,
+    email: String,
+    name: String,
+    status: String,
+}
+impl TryInto<Subscriber> for Task {
+    type Error = String;
+    fn try_into(self) -> Result<Subscriber, Self::Error> {
+        let email = self.email.parse::<Address>().map_err(|x| format!("{x}"))?;
+        let name = SubscriberName::parse(self.name)?;
+        Ok(Subscriber { email, name })
+    }
+}
+
+pub struct NewsletterIssue {
+    pub title: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+pub async fn run_worker_until_stopped(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+) -> Result<(), std::io::Error> {
+    loop {
+        match try_execute_task(&pool, &email_client, &base_url, &hmac_secret).await {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Execute a delivery task",
+    skip_all,
+    fields(newsletter_issue_id=tracing::field::Empty, subscriber_id=tracing::field::Empty),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    let newsletter_issue_id = task.newsletter_issue_id;
+    let subscriber_id = task.subscriber_id;
+    tracing::Span::current()
+        .record(
+            "newsletter_issue_id",
+            tracing::field::display(newsletter_issue_id),
+        )
+        .record("subscriber_id", tracing::field::display(subscriber_id));
+
+    if task.status != SubscriptionStatus::Confirmed.as_str() {
+        tracing::info!("Skipping a subscriber who is no longer confirmed");
+        mark_tasks_skipped(&mut transaction, &[newsletter_issue_id], &[subscriber_id]).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit delivery task")?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let n_attempts = task.n_attempts + 1;
+    let subscriber: Result<Subscriber, _> = task.try_into();
+    match subscriber {
+        Ok(subscriber) => {
+            let issue = get_issue(pool, newsletter_issue_id).await?;
+            let unsubscribe_token = UnsubscribeToken::generate(subscriber_id, hmac_secret);
+            let unsubscribe_link =
+                EmailClient::get_unsubscribe_link(base_url, unsubscribe_token.as_ref());
+            let preferences_token = PreferencesToken::generate(subscriber_id, hmac_secret);
+            let preferences_link =
+                EmailClient::get_preferences_link(base_url, preferences_token.as_ref());
+            let archive_link = EmailClient::get_archive_link(base_url, newsletter_issue_id);
+            let (html_content, text_content) = match render_issue(
+                &issue,
+                &subscriber,
+                &unsubscribe_link,
+                &preferences_link,
+                &archive_link,
+            ) {
+                Ok(rendered) => rendered,
+                Err(e) => {
+                    tracing::error!("Failed to render newsletter issue: {}", e);
+                    mark_tasks_dead_letter(
+                        &mut transaction,
+                        &[newsletter_issue_id],
+                        &[subscriber_id],
+                        &e.to_string(),
+                    )
+                    .await?;
+                    transaction
+                        .commit()
+                        .await
+                        .context("Failed to commit delivery task")?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+            };
+            let outcome = email_client
+                .send_newsletter(
+                    subscriber.name.as_ref().to_owned(),
+                    subscriber.email,
+                    &issue.title,
+                    &html_content,
+                    &text_content,
+                    &unsubscribe_link,
+                )
+                .await;
+            record_send_outcome(
+                &mut transaction,
+                &[newsletter_issue_id],
+                &[subscriber_id],
+                outcome,
+                n_attempts,
+            )
+            .await?;
+        }
+        Err(e) => {
+            tracing::error!("Skipping a subscriber with invalid stored details: {}", e);
+            mark_tasks_dead_letter(&mut transaction, &[newsletter_issue_id], &[subscriber_id], &e)
+                .await?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit delivery task")?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Renders the HTML and text bodies exactly as `subscriber` receives them, footer included.
+pub fn render_issue(
+    issue: &NewsletterIssue,
+    subscriber: &Subscriber,
+    unsubscribe_link: &UnsubscribeLink,
+    preferences_link: &PreferencesLink,
+    archive_link: &ArchiveLink,
+) -> Result<(String, String), TemplateError> {
+    let context = TemplateContext::new(subscriber, &unsubscribe_link.0, &archive_link.0);
+    let html_content = IssueTemplate::parse(issue.html_content.to_owned(), TemplateFormat::Html)?
+        .render(&context)?;
+    let text_content = IssueTemplate::parse(issue.text_content.to_owned(), TemplateFormat::Text)?
+        .render(&context)?;
+    Ok(append_footer(
+        html_content,
+        text_content,
+        unsubscribe_link,
+        preferences_link,
+    ))
+}
+
+/// Adds the unsubscribe and preference links every email we send ends with.
+pub(crate) fn append_footer(
+    html_content: String,
+    text_content: String,
+    unsubscribe_link: &UnsubscribeLink,
+    preferences_link: &PreferencesLink,
+) -> (String, String) {
+    let html_content = format!(
+        "{}<p>Don't want these emails anymore? <a href=\"{}\">Unsubscribe</a> or <a href=\"{}\">manage your preferences</a>.</p>",
+        html_content, unsubscribe_link.0, preferences_link.0
+    );
+    let text_content = format!(
+        "{}\n\nDon't want these emails anymore? Unsubscribe: {}\nManage your preferences: {}",
+        text_content, unsubscribe_link.0, preferences_link.0
+    );
+    (html_content, text_content)
+}
+
+/// Exponential backoff capped at `MAX_RETRY_DELAY`, plus up to 50% random jitter so
+/// that deliveries failing together don't retry in lockstep.
+pub(crate) fn get_retry_delay(n_attempts: i32) -> Duration {
+    let exponent = n_attempts.saturating_sub(1).clamp(0, 16) as u32;
+    let delay = BASE_RETRY_DELAY
+        .saturating_mul(2u32.pow(exponent))
+        .min(MAX_RETRY_DELAY);
+    let jitter = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 2);
+    delay + Duration::from_millis(jitter)
+}
+
+#[tracing::instrument(name = "Dequeue a delivery task", skip(pool))]
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(Transaction<'static, Postgres>, Task)>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let task = sqlx::query_as!(
+        Task,
+        r#"
+            SELECT q.newsletter_issue_id, q.subscriber_id, q.n_attempts, s.email, s.name, s.status
+            FROM issue_delivery_queue q
+            JOIN subscriptions s ON s.id = q.subscriber_id
+            WHERE q.status = 'pending' AND q.execute_after <= now()
+            LIMIT 1
+            FOR UPDATE OF q SKIP LOCKED
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to fetch a pending delivery task")?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+/// Marks the tasks delivered, or schedules a retry, or gives up on them, depending on how
+/// sending went. `n_attempts` counts the attempt that just finished.
+#[tracing::instrument(
+    name = "Record the outcome of a delivery",
+    skip(transaction, outcome)
+)]
+pub(crate) async fn record_send_outcome(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_ids: &[Uuid],
+    subscriber_ids: &[Uuid],
+    outcome: Result<(), SendEmailError>,
+    n_attempts: i32,
+) -> Result<(), anyhow::Error> {
+    match outcome {
+        Ok(_) => mark_tasks_delivered(transaction, newsletter_issue_ids, subscriber_ids).await,
+        Err(e) if e.is_permanent() || n_attempts >= MAX_ATTEMPTS => {
+            tracing::error!("Giving up on delivery after {} attempts: {}", n_attempts, e);
+            mark_tasks_dead_letter(
+                transaction,
+                newsletter_issue_ids,
+                subscriber_ids,
+                &e.to_string(),
+            )
+            .await
+        }
+        Err(e) => {
+            let retry_delay = get_retry_delay(n_attempts);
+            tracing::warn!(
+                "Delivery attempt {} failed, retrying in {:?}: {}",
+                n_attempts,
+                retry_delay,
+                e
+            );
+            reschedule_tasks(
+                transaction,
+                newsletter_issue_ids,
+                subscriber_ids,
+                retry_delay,
+                &e.to_string(),
+            )
+            .await
+        }
+    }
+}
+
+// The tasks below are given as parallel arrays of issue and subscriber ids, one pair per
+// row of `issue_delivery_queue`; a digest settles several rows at once.
+
+#[tracing::instrument(name = "Mark delivery tasks as delivered", skip(transaction))]
+async fn mark_tasks_delivered(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_ids: &[Uuid],
+    subscriber_ids: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE issue_delivery_queue q
+            SET status = 'delivered', delivered_at = now(), n_attempts = q.n_attempts + 1
+            FROM UNNEST($1::uuid[], $2::uuid[]) AS t(newsletter_issue_id, subscriber_id)
+            WHERE q.newsletter_issue_id = t.newsletter_issue_id AND q.subscriber_id = t.subscriber_id
+        "#,
+        newsletter_issue_ids,
+        subscriber_ids,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to mark delivery tasks as delivered")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Mark delivery tasks as skipped", skip(transaction))]
+pub(crate) async fn mark_tasks_skipped(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_ids: &[Uuid],
+    subscriber_ids: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE issue_delivery_queue q
+            SET status = 'skipped'
+            FROM UNNEST($1::uuid[], $2::uuid[]) AS t(newsletter_issue_id, subscriber_id)
+            WHERE q.newsletter_issue_id = t.newsletter_issue_id AND q.subscriber_id = t.subscriber_id
+        "#,
+        newsletter_issue_ids,
+        subscriber_ids,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to mark delivery tasks as skipped")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Reschedule delivery tasks", skip(transaction, last_error))]
+async fn reschedule_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_ids: &[Uuid],
+    subscriber_ids: &[Uuid],
+    retry_delay: Duration,
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
+    let execute_after = chrono::Utc::now() + chrono::Duration::from_std(retry_delay)?;
+    sqlx::query!(
+        r#"
+            UPDATE issue_delivery_queue q
+            SET n_attempts = q.n_attempts + 1, execute_after = $3, last_error = $4
+            FROM UNNEST($1::uuid[], $2::uuid[]) AS t(newsletter_issue_id, subscriber_id)
+            WHERE q.newsletter_issue_id = t.newsletter_issue_id AND q.subscriber_id = t.subscriber_id
+        "#,
+        newsletter_issue_ids,
+        subscriber_ids,
+        execute_after,
+        last_error,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to reschedule delivery tasks")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Mark delivery tasks as dead letter",
+    skip(transaction, last_error)
+)]
+pub(crate) async fn mark_tasks_dead_letter(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_ids: &[Uuid],
+    subscriber_ids: &[Uuid],
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE issue_delivery_queue q
+            SET status = 'dead_letter', n_attempts = q.n_attempts + 1, last_error = $3
+            FROM UNNEST($1::uuid[], $2::uuid[]) AS t(newsletter_issue_id, subscriber_id)
+            WHERE q.newsletter_issue_id = t.newsletter_issue_id AND q.subscriber_id = t.subscriber_id
+        "#,
+        newsletter_issue_ids,
+        subscriber_ids,
+        last_error,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to mark delivery tasks as dead letter")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get newsletter issue", skip(pool))]
+async fn get_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+            SELECT title, html_content, text_content
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve newsletter issue")?;
+    Ok(issue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_retry_delay, MAX_RETRY_DELAY};
+    use std::time::Duration;
+
+    #[test]
+    fn first_retry_waits_at_least_the_base_delay() {
+        let delay = get_retry_delay(1);
+        assert!(delay >= Duration::from_secs(30));
+        assert!(delay <= Duration::from_secs(45));
+    }
+    #[test]
+    fn retry_delay_grows_exponentially() {
+        let delay = get_retry_delay(3);
+        assert!(delay >= Duration::from_secs(120));
+        assert!(delay <= Duration::from_secs(180));
+    }
+    #[test]
+    fn retry_delay_is_capped() {
+        let delay = get_retry_delay(40);
+        assert!(delay >= MAX_RETRY_DELAY);
+        assert!(delay <= MAX_RETRY_DELAY + MAX_RETRY_DELAY / 2);
+    }
+}
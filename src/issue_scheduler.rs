@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::Segment;
+use crate::routes::{enqueue_delivery_tasks, get_confirmed_subscribers};
+
+pub enum SchedulerOutcome {
+    IssuePublished,
+    NothingDue,
+}
+
+pub async fn run_scheduler_until_stopped(pool: PgPool) -> Result<(), std::io::Error> {
+    loop {
+        match try_publish_due_issue(&pool).await {
+            Ok(SchedulerOutcome::NothingDue) => tokio::time::sleep(Duration::from_secs(5)).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(SchedulerOutcome::IssuePublished) => {}
+        }
+    }
+}
+
+/// Fans a due scheduled issue out to the delivery queue. The issue stays `scheduled`
+/// until this commits, so a restart simply picks it up again.
+#[tracing::instrument(
+    name = "Publish a scheduled issue",
+    skip_all,
+    fields(newsletter_issue_id=tracing::field::Empty),
+    err
+)]
+pub async fn try_publish_due_issue(pool: &PgPool) -> Result<SchedulerOutcome, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let due_issue = sqlx::query!(
+        r#"
+            SELECT newsletter_issue_id, list_id, segment AS "segment: Json<Segment>"
+            FROM newsletter_issues
+            WHERE status = 'scheduled' AND send_at <= now()
+            ORDER BY send_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to fetch a due scheduled issue")?;
+    let Some(due_issue) = due_issue else {
+        return Ok(SchedulerOutcome::NothingDue);
+    };
+    let newsletter_issue_id: Uuid = due_issue.newsletter_issue_id;
+    tracing::Span::current().record(
+        "newsletter_issue_id",
+        tracing::field::display(newsletter_issue_id),
+    );
+
+    let subscriber_ids = get_confirmed_subscribers(
+        &mut *transaction,
+        due_issue.list_id,
+        due_issue.segment.as_deref(),
+    )
+    .await?;
+    enqueue_delivery_tasks(&mut transaction, newsletter_issue_id, &subscriber_ids).await?;
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = 'published', published_at = now()
+            WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark scheduled issue as published")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit scheduled issue")?;
+    Ok(SchedulerOutcome::IssuePublished)
+}
@@ -3,4 +3,11 @@ pub mod routes;
 pub mod startup;
 pub mod telemetry;
 pub mod domain;
-pub mod email_client;
\ No newline at end of file
+pub mod email_client;
+pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod idempotency;
+pub mod authentication;
+pub mod digest_worker;
+pub mod confirmation_worker;
+pub mod session;
\ No newline at end of file
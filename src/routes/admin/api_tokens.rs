@@ -0,0 +1,182 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::authenticate_admin;
+use crate::domain::{ApiScope, ApiToken};
+
+#[derive(serde::Deserialize)]
+pub struct NewApiTokenData {
+    name: String,
+    scopes: Vec<String>,
+    /// The token never expires if missing.
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ApiTokenDetails {
+    token_id: Uuid,
+    name: String,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    details: ApiTokenDetails,
+    /// Only returned here, we keep nothing but its hash.
+    token: String,
+}
+
+/// Tokens are managed with an admin's password only, a token can't mint another one.
+#[tracing::instrument(name = "Create an API token", skip(body, pool, request))]
+pub async fn create_api_token(
+    body: web::Json<NewApiTokenData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let user_id = match authenticate_admin(&request, &pool).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    let body = body.into_inner();
+    let name = body.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("A token needs a name");
+    }
+    if body.scopes.is_empty() {
+        return HttpResponse::BadRequest().body("A token needs at least one scope");
+    }
+    let mut scopes = match body
+        .scopes
+        .into_iter()
+        .map(ApiScope::try_from)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(scopes) => scopes,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    scopes.sort();
+    scopes.dedup();
+    if body
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return HttpResponse::BadRequest().body("The expiry must be in the future");
+    }
+    let token = ApiToken::generate();
+    match insert_api_token(&pool, user_id, name, &scopes, body.expires_at, &token).await {
+        Ok(details) => HttpResponse::Created().json(CreatedApiToken {
+            details,
+            token: token.expose_secret().clone(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "List API tokens", skip(pool, request))]
+pub async fn list_api_tokens(pool: web::Data<PgPool>, request: HttpRequest) -> HttpResponse {
+    let user_id = match authenticate_admin(&request, &pool).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    match get_api_tokens(&pool, user_id).await {
+        Ok(tokens) => HttpResponse::Ok().json(tokens),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Revoke an API token", skip(pool, request))]
+pub async fn revoke_api_token(
+    token_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let user_id = match authenticate_admin(&request, &pool).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    match remove_api_token(&pool, user_id, *token_id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Saving new API token in the database", skip(pool, token))]
+async fn insert_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+    expires_at: Option<DateTime<Utc>>,
+    token: &ApiToken,
+) -> Result<ApiTokenDetails, sqlx::Error> {
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query_as!(
+        ApiTokenDetails,
+        r#"
+            INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, now(), $6)
+            RETURNING token_id, name, scopes, created_at, expires_at, last_used_at
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        token.hash(),
+        &scopes,
+        expires_at,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get API tokens", skip(pool))]
+async fn get_api_tokens(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiTokenDetails>, sqlx::Error> {
+    sqlx::query_as!(
+        ApiTokenDetails,
+        r#"
+            SELECT token_id, name, scopes, created_at, expires_at, last_used_at
+            FROM api_tokens
+            WHERE user_id = $1
+            ORDER BY created_at
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Delete an API token", skip(pool))]
+async fn remove_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    // Scoped to the owner, another admin's token is as good as missing.
+    let result = sqlx::query!(
+        r#"DELETE FROM api_tokens WHERE token_id = $1 AND user_id = $2"#,
+        token_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.rows_affected() == 1)
+}
@@ -0,0 +1,116 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use minijinja::HtmlEscape;
+use sqlx::PgPool;
+
+use super::require_login;
+use crate::authentication::get_username;
+use crate::routes::{render_flash_messages, see_other};
+use crate::session::TypedSession;
+
+struct Overview {
+    confirmed: i64,
+    pending_confirmation: i64,
+    drafts: i64,
+    scheduled: i64,
+    dead_letters: i64,
+}
+
+#[tracing::instrument(name = "Show the admin dashboard", skip(pool, session, flash_messages))]
+pub async fn admin_dashboard(
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> HttpResponse {
+    let user_id = match require_login(&session) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    let username = match get_username(user_id, &pool).await {
+        Ok(Some(username)) => username,
+        // The user was removed while still logged in.
+        Ok(None) => {
+            session.log_out();
+            return see_other("/login");
+        }
+        Err(e) => {
+            tracing::error!("Failed to get username: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let overview = match get_overview(&pool).await {
+        Ok(overview) => overview,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    {}
+    <p>Welcome {}!</p>
+    <ul>
+        <li>{} confirmed subscribers, {} waiting for confirmation</li>
+        <li>{} drafts, {} scheduled issues</li>
+        <li>{} dead-lettered deliveries</li>
+    </ul>
+    <p>Available actions:</p>
+    <ol>
+        <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+        <li><a href="/admin/password">Change password</a></li>
+        <li>
+            <form name="logoutForm" action="/admin/logout" method="post">
+                <input type="submit" value="Logout">
+            </form>
+        </li>
+    </ol>
+</body>
+</html>"#,
+            render_flash_messages(&flash_messages),
+            HtmlEscape(&username),
+            overview.confirmed,
+            overview.pending_confirmation,
+            overview.drafts,
+            overview.scheduled,
+            overview.dead_letters,
+        ))
+}
+
+#[tracing::instrument(name = "Log out", skip(session))]
+pub async fn log_out(session: TypedSession) -> HttpResponse {
+    if require_login(&session).is_err() {
+        return see_other("/login");
+    }
+    session.log_out();
+    FlashMessage::info("You have successfully logged out.").send();
+    see_other("/login")
+}
+
+#[tracing::instrument(name = "Get dashboard overview", skip(pool))]
+async fn get_overview(pool: &PgPool) -> Result<Overview, sqlx::Error> {
+    sqlx::query_as!(
+        Overview,
+        r#"
+            SELECT
+                (SELECT COUNT(*) FROM subscriptions WHERE status = 'confirmed') AS "confirmed!",
+                (SELECT COUNT(*) FROM subscriptions WHERE status = 'pending_confirmation')
+                    AS "pending_confirmation!",
+                (SELECT COUNT(*) FROM newsletter_issues WHERE status = 'draft') AS "drafts!",
+                (SELECT COUNT(*) FROM newsletter_issues WHERE status = 'scheduled') AS "scheduled!",
+                (SELECT COUNT(*) FROM issue_delivery_queue WHERE status = 'dead_letter')
+                    AS "dead_letters!"
+        "#,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
@@ -0,0 +1,48 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::authenticate_admin;
+
+#[derive(serde::Serialize)]
+pub struct DeadLetter {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_id: Uuid,
+    subscriber_email: String,
+    n_attempts: i32,
+    last_error: Option<String>,
+}
+
+#[tracing::instrument(name = "List dead-lettered deliveries", skip(pool, request))]
+pub async fn list_dead_letters(pool: web::Data<PgPool>, request: HttpRequest) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    match get_dead_letters(&pool).await {
+        Ok(dead_letters) => HttpResponse::Ok().json(dead_letters),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Get dead-lettered deliveries", skip(pool))]
+async fn get_dead_letters(pool: &PgPool) -> Result<Vec<DeadLetter>, sqlx::Error> {
+    sqlx::query_as!(
+        DeadLetter,
+        r#"
+            SELECT q.newsletter_issue_id, i.title, q.subscriber_id,
+                   s.email AS subscriber_email, q.n_attempts, q.last_error
+            FROM issue_delivery_queue q
+            JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+            JOIN subscriptions s ON s.id = q.subscriber_id
+            WHERE q.status = 'dead_letter'
+            ORDER BY i.published_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
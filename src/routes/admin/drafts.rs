@@ -0,0 +1,603 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use lettre::Address;
+use sqlx::types::Json;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::authenticate_admin;
+use crate::domain::{PreferencesToken, Segment, Subscriber, SubscriberName, UnsubscribeToken};
+use crate::email_client::{EmailClient, PreferencesLink, UnsubscribeLink};
+use crate::issue_delivery_worker::{render_issue, NewsletterIssue};
+use crate::routes::{
+    enqueue_delivery_tasks, get_confirmed_subscribers, plain_text_content, resolve_list,
+    validate_templates, AcceptedIssue,
+};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+
+/// Test sends go to a handful of editors, not to a list.
+const MAX_TEST_RECIPIENTS: usize = 10;
+/// Who a draft is rendered for when no subscriber is given.
+const DEFAULT_NAME: &str = "Subscriber";
+const DEFAULT_EMAIL: &str = "subscriber@example.com";
+
+#[derive(serde::Deserialize)]
+pub struct DraftData {
+    subject: String,
+    #[serde(alias = "content")]
+    html_content: String,
+    text_content: Option<String>,
+    /// Slug of the list the issue is meant for, the default list if missing.
+    list: Option<String>,
+    segment: Option<Segment>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Draft {
+    newsletter_issue_id: Uuid,
+    list: String,
+    segment: Option<Json<Segment>>,
+    subject: String,
+    html_content: String,
+    text_content: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DraftSummary {
+    newsletter_issue_id: Uuid,
+    list: String,
+    subject: String,
+    updated_at: DateTime<Utc>,
+}
+
+/// Either a real subscriber or a made up one, e.g. `?name=Ursula&email=ursula@example.com`.
+#[derive(serde::Deserialize)]
+pub struct PreviewParameters {
+    subscriber_id: Option<Uuid>,
+    name: Option<String>,
+    email: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Preview {
+    subject: String,
+    html_content: String,
+    text_content: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TestSendData {
+    recipients: Vec<String>,
+    name: Option<String>,
+}
+
+struct PublishedDraft {
+    list_id: Uuid,
+    segment: Option<Json<Segment>>,
+}
+
+/// Send `{}` to publish right away. The body is required so a `send_at` that fails to parse
+/// is rejected rather than silently publishing to the whole list.
+#[derive(serde::Deserialize)]
+pub struct PublishDraftData {
+    send_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Create a draft", skip(body, pool, request))]
+pub async fn create_draft(
+    body: web::Json<DraftData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    let text_content = match get_text_content(&body) {
+        Ok(text_content) => text_content,
+        Err(response) => return response,
+    };
+    let list_id = match resolve_list(pool.get_ref(), body.list.as_deref()).await {
+        Ok(list_id) => list_id,
+        Err(response) => return response,
+    };
+    match insert_draft(
+        &pool,
+        list_id,
+        body.segment.as_ref(),
+        &body.subject,
+        &body.html_content,
+        &text_content,
+    )
+    .await
+    {
+        Ok(draft) => HttpResponse::Created().json(draft),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "List drafts", skip(pool, request))]
+pub async fn list_drafts(pool: web::Data<PgPool>, request: HttpRequest) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    match get_drafts(&pool).await {
+        Ok(drafts) => HttpResponse::Ok().json(drafts),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Get a draft", skip(pool, request))]
+pub async fn get_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    match fetch_draft(&pool, *newsletter_issue_id).await {
+        Ok(Some(draft)) => HttpResponse::Ok().json(draft),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Update a draft", skip(body, pool, request))]
+pub async fn update_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<DraftData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    let text_content = match get_text_content(&body) {
+        Ok(text_content) => text_content,
+        Err(response) => return response,
+    };
+    // Without a list the draft stays on the one it was created for.
+    let list_id = match &body.list {
+        Some(slug) => match resolve_list(pool.get_ref(), Some(slug)).await {
+            Ok(list_id) => Some(list_id),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+    match store_draft(
+        &pool,
+        *newsletter_issue_id,
+        list_id,
+        body.segment.as_ref(),
+        &body.subject,
+        &body.html_content,
+        &text_content,
+    )
+    .await
+    {
+        Ok(Some(draft)) => HttpResponse::Ok().json(draft),
+        // Once published or scheduled the issue is no longer a draft to edit.
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Delete a draft", skip(pool, request))]
+pub async fn delete_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    match remove_draft(&pool, *newsletter_issue_id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(
+    name = "Preview a draft",
+    skip(parameters, pool, base_url, hmac_secret, request)
+)]
+pub async fn preview_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    parameters: web::Query<PreviewParameters>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    let draft = match fetch_draft(&pool, *newsletter_issue_id).await {
+        Ok(Some(draft)) => draft,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let (subscriber, (unsubscribe_link, preferences_link)) = match parameters.subscriber_id {
+        Some(subscriber_id) => {
+            let subscriber = match get_subscriber(&pool, subscriber_id).await {
+                Ok(Some(subscriber)) => subscriber,
+                Ok(None) => return HttpResponse::NotFound().finish(),
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+            let unsubscribe_token = UnsubscribeToken::generate(subscriber_id, &hmac_secret.0);
+            let unsubscribe_link =
+                EmailClient::get_unsubscribe_link(&base_url.0, unsubscribe_token.as_ref());
+            let preferences_token = PreferencesToken::generate(subscriber_id, &hmac_secret.0);
+            let preferences_link =
+                EmailClient::get_preferences_link(&base_url.0, preferences_token.as_ref());
+            (subscriber, (unsubscribe_link, preferences_link))
+        }
+        None => {
+            let subscriber = match parse_subscriber(
+                parameters.name.as_deref().unwrap_or(DEFAULT_NAME),
+                parameters.email.as_deref().unwrap_or(DEFAULT_EMAIL),
+            ) {
+                Ok(subscriber) => subscriber,
+                Err(e) => return HttpResponse::BadRequest().body(e),
+            };
+            (subscriber, get_placeholder_links(&base_url.0))
+        }
+    };
+    let archive_link = EmailClient::get_archive_link(&base_url.0, draft.newsletter_issue_id);
+    let issue = NewsletterIssue::from(draft);
+    match render_issue(
+        &issue,
+        &subscriber,
+        &unsubscribe_link,
+        &preferences_link,
+        &archive_link,
+    ) {
+        Ok((html_content, text_content)) => HttpResponse::Ok().json(Preview {
+            subject: issue.title,
+            html_content,
+            text_content,
+        }),
+        Err(e) => {
+            tracing::error!("Failed to render draft: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Send a draft to test recipients",
+    skip(body, pool, email_client, base_url, request)
+)]
+pub async fn test_send_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<TestSendData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    if body.recipients.is_empty() || body.recipients.len() > MAX_TEST_RECIPIENTS {
+        return HttpResponse::BadRequest().body(format!(
+            "Expected between 1 and {} recipients",
+            MAX_TEST_RECIPIENTS
+        ));
+    }
+    let recipients: Result<Vec<Subscriber>, String> = body
+        .recipients
+        .iter()
+        .map(|email| parse_subscriber(body.name.as_deref().unwrap_or(DEFAULT_NAME), email))
+        .collect();
+    let recipients = match recipients {
+        Ok(recipients) => recipients,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let draft = match fetch_draft(&pool, *newsletter_issue_id).await {
+        Ok(Some(draft)) => draft,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let archive_link = EmailClient::get_archive_link(&base_url.0, draft.newsletter_issue_id);
+    let (unsubscribe_link, preferences_link) = get_placeholder_links(&base_url.0);
+    let issue = NewsletterIssue::from(draft);
+    let subject = format!("[Test] {}", issue.title);
+    for recipient in recipients {
+        let (html_content, text_content) = match render_issue(
+            &issue,
+            &recipient,
+            &unsubscribe_link,
+            &preferences_link,
+            &archive_link,
+        ) {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                tracing::error!("Failed to render draft: {}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
+        if email_client
+            .send_newsletter(
+                recipient.name.as_ref().to_owned(),
+                recipient.email,
+                &subject,
+                &html_content,
+                &text_content,
+                &unsubscribe_link,
+            )
+            .await
+            .is_err()
+        {
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    HttpResponse::NoContent().finish()
+}
+
+#[tracing::instrument(name = "Publish a draft", skip(body, pool, request))]
+pub async fn publish_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<PublishDraftData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    let send_at = body.send_at.filter(|send_at| *send_at > Utc::now());
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let published =
+        match mark_draft_published(&mut transaction, *newsletter_issue_id, send_at).await {
+            Ok(Some(published)) => published,
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+    // Scheduled issues are fanned out by the scheduler once they are due.
+    if send_at.is_none() {
+        let subscriber_ids = match get_confirmed_subscribers(
+            &mut *transaction,
+            published.list_id,
+            published.segment.as_deref(),
+        )
+        .await
+        {
+            Ok(subscriber_ids) => subscriber_ids,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        if enqueue_delivery_tasks(&mut transaction, *newsletter_issue_id, &subscriber_ids)
+            .await
+            .is_err()
+        {
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Accepted().json(AcceptedIssue {
+        newsletter_issue_id: *newsletter_issue_id,
+        send_at,
+    })
+}
+
+impl From<Draft> for NewsletterIssue {
+    fn from(draft: Draft) -> Self {
+        Self {
+            title: draft.subject,
+            html_content: draft.html_content,
+            text_content: draft.text_content,
+        }
+    }
+}
+
+fn get_text_content(body: &DraftData) -> Result<String, HttpResponse> {
+    let text_content = plain_text_content(&body.html_content, body.text_content.as_deref());
+    validate_templates(&body.html_content, &text_content)?;
+    Ok(text_content)
+}
+
+fn parse_subscriber(name: &str, email: &str) -> Result<Subscriber, String> {
+    let email = email.parse::<Address>().map_err(|e| format!("{e}"))?;
+    let name = SubscriberName::parse(name.to_owned())?;
+    Ok(Subscriber { email, name })
+}
+
+/// Made up recipients have no subscription, so their unsubscribe and preferences links go nowhere.
+fn get_placeholder_links(base_url: &str) -> (UnsubscribeLink, PreferencesLink) {
+    (
+        EmailClient::get_unsubscribe_link(base_url, "preview"),
+        EmailClient::get_preferences_link(base_url, "preview"),
+    )
+}
+
+#[tracing::instrument(
+    name = "Insert a draft",
+    skip(pool, subject, html_content, text_content)
+)]
+async fn insert_draft(
+    pool: &PgPool,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<Draft, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+            INSERT INTO newsletter_issues (newsletter_issue_id, list_id, segment, title, html_content, text_content, status)
+            VALUES ($1, $2, $3, $4, $5, $6, 'draft')
+            RETURNING newsletter_issue_id,
+                      (SELECT slug FROM lists l WHERE l.list_id = newsletter_issues.list_id) AS "list!",
+                      segment AS "segment: Json<Segment>",
+                      title AS subject, html_content, text_content, updated_at
+        "#,
+        Uuid::new_v4(),
+        list_id,
+        segment.map(Json) as _,
+        subject,
+        html_content,
+        text_content,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get drafts", skip(pool))]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<DraftSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        DraftSummary,
+        r#"
+            SELECT i.newsletter_issue_id, l.slug AS list, i.title AS subject, i.updated_at
+            FROM newsletter_issues i
+            JOIN lists l ON l.list_id = i.list_id
+            WHERE i.status = 'draft'
+            ORDER BY i.updated_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Fetch a draft", skip(pool))]
+async fn fetch_draft(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+            SELECT i.newsletter_issue_id, l.slug AS list, i.segment AS "segment: Json<Segment>",
+                   i.title AS subject, i.html_content, i.text_content, i.updated_at
+            FROM newsletter_issues i
+            JOIN lists l ON l.list_id = i.list_id
+            WHERE i.newsletter_issue_id = $1 AND i.status = 'draft'
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(
+    name = "Store a draft",
+    skip(pool, subject, html_content, text_content)
+)]
+async fn store_draft(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    list_id: Option<Uuid>,
+    segment: Option<&Segment>,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<Option<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+            UPDATE newsletter_issues
+            SET list_id = COALESCE($2, list_id), segment = $3, title = $4, html_content = $5,
+                text_content = $6, updated_at = now()
+            WHERE newsletter_issue_id = $1 AND status = 'draft'
+            RETURNING newsletter_issue_id,
+                      (SELECT slug FROM lists l WHERE l.list_id = newsletter_issues.list_id) AS "list!",
+                      segment AS "segment: Json<Segment>",
+                      title AS subject, html_content, text_content, updated_at
+        "#,
+        newsletter_issue_id,
+        list_id,
+        segment.map(Json) as _,
+        subject,
+        html_content,
+        text_content,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Remove a draft", skip(pool))]
+async fn remove_draft(pool: &PgPool, newsletter_issue_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            DELETE FROM newsletter_issues
+            WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "Mark a draft as published", skip(transaction))]
+async fn mark_draft_published(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    send_at: Option<DateTime<Utc>>,
+) -> Result<Option<PublishedDraft>, sqlx::Error> {
+    sqlx::query_as!(
+        PublishedDraft,
+        r#"
+            UPDATE newsletter_issues
+            SET status = CASE WHEN $2::timestamptz IS NULL THEN 'published' ELSE 'scheduled' END,
+                published_at = CASE WHEN $2::timestamptz IS NULL THEN now() END,
+                send_at = $2,
+                updated_at = now()
+            WHERE newsletter_issue_id = $1 AND status = 'draft'
+            RETURNING list_id, segment AS "segment: Json<Segment>"
+        "#,
+        newsletter_issue_id,
+        send_at,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get a subscriber to preview for", skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT email, name FROM subscriptions WHERE id = $1",
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    // Stored details were validated on the way in, a failure here is treated as missing.
+    Ok(row.and_then(|row| parse_subscriber(&row.name, &row.email).ok()))
+}
@@ -0,0 +1,125 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+use super::authenticate_admin;
+use crate::domain::{ListSlug, DEFAULT_LIST_SLUG};
+
+#[derive(serde::Deserialize)]
+pub struct ListData {
+    slug: String,
+    name: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct MailingList {
+    list_id: Uuid,
+    slug: String,
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(body, pool, request))]
+pub async fn create_list(
+    body: web::Json<ListData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    let slug = match ListSlug::parse(body.slug.clone()) {
+        Ok(slug) => slug,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    if body.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("A list needs a name");
+    }
+    match insert_list(&pool, &slug, body.name.trim()).await {
+        Ok(Some(list)) => HttpResponse::Created().json(list),
+        Ok(None) => HttpResponse::Conflict().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "List mailing lists", skip(pool, request))]
+pub async fn list_lists(pool: web::Data<PgPool>, request: HttpRequest) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    match get_lists(&pool).await {
+        Ok(lists) => HttpResponse::Ok().json(lists),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Looks up the list a request names by slug, falling back to the default list.
+pub async fn resolve_list(
+    executor: impl Executor<'_, Database = Postgres>,
+    slug: Option<&str>,
+) -> Result<Uuid, HttpResponse> {
+    let slug = ListSlug::parse(slug.unwrap_or(DEFAULT_LIST_SLUG).to_owned())
+        .map_err(|e| HttpResponse::BadRequest().body(e))?;
+    match get_list_id(executor, &slug).await {
+        Ok(Some(list_id)) => Ok(list_id),
+        Ok(None) => Err(HttpResponse::NotFound().finish()),
+        Err(_) => Err(HttpResponse::InternalServerError().finish()),
+    }
+}
+
+#[tracing::instrument(name = "Get list_id from slug", skip(executor))]
+pub async fn get_list_id(
+    executor: impl Executor<'_, Database = Postgres>,
+    slug: &ListSlug,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT list_id FROM lists WHERE slug = $1"#,
+        slug.as_ref()
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| r.list_id))
+}
+
+#[tracing::instrument(name = "Insert a mailing list", skip(pool))]
+async fn insert_list(
+    pool: &PgPool,
+    slug: &ListSlug,
+    name: &str,
+) -> Result<Option<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"
+            INSERT INTO lists (list_id, slug, name)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (slug) DO NOTHING
+            RETURNING list_id, slug, name
+        "#,
+        Uuid::new_v4(),
+        slug.as_ref(),
+        name,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get mailing lists", skip(pool))]
+async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"SELECT list_id, slug, name FROM lists ORDER BY created_at"#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
@@ -0,0 +1,88 @@
+mod api_tokens;
+mod dashboard;
+mod dead_letters;
+mod drafts;
+mod lists;
+mod newsletter_form;
+mod password;
+mod scheduled_issues;
+mod subscriber_export;
+mod subscriber_import;
+mod subscriber_tags;
+mod subscribers;
+mod users;
+
+pub use api_tokens::*;
+pub use dashboard::*;
+pub use dead_letters::*;
+pub use drafts::*;
+pub use lists::*;
+pub use newsletter_form::*;
+pub use password::*;
+pub use scheduled_issues::*;
+pub use subscriber_export::*;
+pub use subscriber_import::*;
+pub use subscriber_tags::*;
+pub use subscribers::*;
+pub use users::*;
+
+use actix_web::{HttpRequest, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{
+    basic_authentication, get_unauthorized_response, validate_credentials,
+};
+use crate::routes::see_other;
+use crate::session::TypedSession;
+
+/// Basic auth against `users`, the same check `publish_newsletter` performs.
+async fn authenticate_admin(request: &HttpRequest, pool: &PgPool) -> Result<Uuid, HttpResponse> {
+    let credentials = match basic_authentication(request.headers()) {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            tracing::error!("Failed to authorize: {}", e);
+            return Err(get_unauthorized_response().finish());
+        }
+    };
+    validate_credentials(credentials, pool)
+        .await
+        .map_err(|mut http_response| {
+            tracing::error!("Failed to validate credentials");
+            http_response.finish()
+        })
+}
+
+/// `authenticate_admin`, restricted to admins allowed to manage other admins.
+async fn authenticate_superuser(
+    request: &HttpRequest,
+    pool: &PgPool,
+) -> Result<Uuid, HttpResponse> {
+    let user_id = authenticate_admin(request, pool).await?;
+    let row = sqlx::query!(
+        r#"SELECT is_superuser FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+    if !row.is_superuser {
+        return Err(HttpResponse::Forbidden().finish());
+    }
+    Ok(user_id)
+}
+
+/// The admin logged in through `/login`, the pages counterpart of `authenticate_admin`.
+fn require_login(session: &TypedSession) -> Result<Uuid, HttpResponse> {
+    match session.get_user_id() {
+        Ok(Some(user_id)) => Ok(user_id),
+        Ok(None) => Err(see_other("/login")),
+        Err(e) => {
+            tracing::error!("Failed to read the session: {}", e);
+            Err(HttpResponse::InternalServerError().finish())
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use minijinja::HtmlEscape;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::require_login;
+use crate::domain::DEFAULT_LIST_SLUG;
+use crate::idempotency::IdempotencyKey;
+use crate::routes::{publish_issue, render_flash_messages, see_other, BodyData};
+use crate::session::TypedSession;
+
+/// The compose form posts the same fields `POST /newsletter` takes as JSON, plus a key
+/// generated with the form so a double submit publishes only once.
+#[derive(serde::Deserialize)]
+pub struct NewsletterFormData {
+    idempotency_key: String,
+    #[serde(flatten)]
+    issue: BodyData,
+}
+
+struct ListOption {
+    slug: String,
+    name: String,
+}
+
+#[tracing::instrument(name = "Show the newsletter form", skip(pool, session, flash_messages))]
+pub async fn newsletter_form(
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> HttpResponse {
+    if let Err(response) = require_login(&session) {
+        return response;
+    }
+    let lists = match get_list_options(&pool).await {
+        Ok(lists) => lists,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let mut options = String::new();
+    for list in lists {
+        writeln!(
+            options,
+            r#"<option value="{}"{}>{}</option>"#,
+            HtmlEscape(&list.slug),
+            if list.slug == DEFAULT_LIST_SLUG {
+                " selected"
+            } else {
+                ""
+            },
+            HtmlEscape(&list.name)
+        )
+        .unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Send a newsletter issue</title>
+</head>
+<body>
+    {}
+    <form action="/admin/newsletters" method="post">
+        <label>List
+            <select name="list">
+                {}
+            </select>
+        </label>
+        <br>
+        <label>Subject
+            <input type="text" placeholder="Enter the issue subject" name="subject">
+        </label>
+        <br>
+        <label>HTML content
+            <textarea placeholder="Enter the content in HTML format" name="html_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <label>Plain text content, derived from the HTML if left empty
+            <textarea placeholder="Enter the content in plain text" name="text_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <input hidden type="text" name="idempotency_key" value="{}">
+        <button type="submit">Publish</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            render_flash_messages(&flash_messages),
+            options,
+            Uuid::new_v4(),
+        ))
+}
+
+#[tracing::instrument(name = "Publish a newsletter from the form", skip(form, pool, session))]
+pub async fn submit_newsletter_form(
+    form: web::Form<NewsletterFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> HttpResponse {
+    let user_id = match require_login(&session) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    let form = form.into_inner();
+    let idempotency_key = match IdempotencyKey::parse(form.idempotency_key) {
+        Ok(idempotency_key) => idempotency_key,
+        Err(e) => {
+            tracing::error!("Invalid idempotency key: {}", e);
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+    let response = publish_issue(&pool, user_id, Some(idempotency_key), &form.issue).await;
+    match response.status() {
+        StatusCode::ACCEPTED => FlashMessage::info(
+            "The newsletter issue has been accepted - emails will go out shortly.",
+        )
+        .send(),
+        status if status.is_client_error() => {
+            FlashMessage::error("The newsletter issue was rejected, check its content and list.")
+                .send()
+        }
+        _ => return response,
+    }
+    see_other("/admin/newsletters")
+}
+
+#[tracing::instrument(name = "Get list options", skip(pool))]
+async fn get_list_options(pool: &PgPool) -> Result<Vec<ListOption>, sqlx::Error> {
+    sqlx::query_as!(
+        ListOption,
+        r#"SELECT slug, name FROM lists ORDER BY created_at"#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
@@ -0,0 +1,127 @@
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use super::require_login;
+use crate::authentication::{self, get_username, validate_credentials, Credentials};
+use crate::domain::AdminPassword;
+use crate::routes::{render_flash_messages, see_other};
+use crate::session::TypedSession;
+
+#[derive(serde::Deserialize)]
+pub struct PasswordFormData {
+    current_password: Secret<String>,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+pub async fn change_password_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> HttpResponse {
+    if let Err(response) = require_login(&session) {
+        return response;
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Change Password</title>
+</head>
+<body>
+    {}
+    <form action="/admin/password" method="post">
+        <label>Current password
+            <input type="password" placeholder="Enter current password" name="current_password">
+        </label>
+        <br>
+        <label>New password
+            <input type="password" placeholder="Enter new password" name="new_password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input type="password" placeholder="Type the new password again" name="new_password_check">
+        </label>
+        <br>
+        <button type="submit">Change password</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            render_flash_messages(&flash_messages)
+        ))
+}
+
+#[tracing::instrument(name = "Change password", skip(form, pool, session))]
+pub async fn change_password(
+    form: web::Form<PasswordFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> HttpResponse {
+    let user_id = match require_login(&session) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    let form = form.into_inner();
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different new passwords - the field values must match.",
+        )
+        .send();
+        return see_other("/admin/password");
+    }
+    if form.new_password.expose_secret() == form.current_password.expose_secret() {
+        FlashMessage::error("The new password must differ from the current one.").send();
+        return see_other("/admin/password");
+    }
+    let new_password = match AdminPassword::parse(form.new_password) {
+        Ok(new_password) => new_password,
+        Err(e) => {
+            FlashMessage::error(format!("{}.", e)).send();
+            return see_other("/admin/password");
+        }
+    };
+    let username = match get_username(user_id, &pool).await {
+        Ok(Some(username)) => username,
+        Ok(None) => {
+            session.log_out();
+            return see_other("/login");
+        }
+        Err(e) => {
+            tracing::error!("Failed to get username: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let credentials = Credentials {
+        username,
+        password: form.current_password,
+    };
+    if let Err(mut http_response) = validate_credentials(credentials, &pool).await {
+        let response = http_response.finish();
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return response;
+        }
+        FlashMessage::error("The current password is incorrect.").send();
+        return see_other("/admin/password");
+    }
+    if let Err(e) = authentication::change_password(user_id, new_password, &pool).await {
+        tracing::error!("Failed to change password: {:?}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    // Sessions opened with the old password end here, this one carries on under a new key.
+    if TypedSession::log_out_everywhere(user_id, &pool)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    session.renew();
+    FlashMessage::info("Your password has been changed.").send();
+    see_other("/admin/password")
+}
@@ -0,0 +1,126 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::authenticate_admin;
+
+#[derive(serde::Serialize)]
+pub struct ScheduledIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    send_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleData {
+    send_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List scheduled issues", skip(pool, request))]
+pub async fn list_scheduled_issues(pool: web::Data<PgPool>, request: HttpRequest) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    match get_scheduled_issues(&pool).await {
+        Ok(scheduled_issues) => HttpResponse::Ok().json(scheduled_issues),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Reschedule an issue", skip(body, pool, request))]
+pub async fn reschedule_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<RescheduleData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    match update_send_at(&pool, *newsletter_issue_id, body.send_at).await {
+        Ok(Some(scheduled_issue)) => HttpResponse::Ok().json(scheduled_issue),
+        // Already published or cancelled, either way there is nothing left to move.
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Cancel a scheduled issue", skip(pool, request))]
+pub async fn cancel_scheduled_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    match cancel_issue(&pool, *newsletter_issue_id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Get scheduled issues", skip(pool))]
+async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+            SELECT newsletter_issue_id, title, send_at
+            FROM newsletter_issues
+            WHERE status = 'scheduled'
+            ORDER BY send_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Update send_at of a scheduled issue", skip(pool))]
+async fn update_send_at(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    send_at: DateTime<Utc>,
+) -> Result<Option<ScheduledIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+            UPDATE newsletter_issues
+            SET send_at = $2
+            WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+            RETURNING newsletter_issue_id, title, send_at
+        "#,
+        newsletter_issue_id,
+        send_at,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Mark a scheduled issue as cancelled", skip(pool))]
+async fn cancel_issue(pool: &PgPool, newsletter_issue_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = 'cancelled'
+            WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.rows_affected() == 1)
+}
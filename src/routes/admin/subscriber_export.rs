@@ -0,0 +1,178 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::authentication::authenticate;
+use crate::domain::{ApiScope, SubscriptionStatus};
+
+/// How many encoded rows may wait for a slow client before we stop reading from Postgres.
+const EXPORT_BUFFER: usize = 64;
+const CSV_HEADER: [&str; 9] = [
+    "id",
+    "list",
+    "email",
+    "name",
+    "status",
+    "digest_frequency",
+    "consent_source",
+    "subscribed_at",
+    "tags",
+];
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    #[serde(alias = "jsonl")]
+    Ndjson,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportParameters {
+    format: Option<ExportFormat>,
+    status: Option<String>,
+    list: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ExportedSubscriber {
+    id: Uuid,
+    list: String,
+    email: String,
+    name: String,
+    status: String,
+    digest_frequency: String,
+    consent_source: String,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+}
+
+type Chunk = Result<web::Bytes, sqlx::Error>;
+
+#[tracing::instrument(name = "Export subscribers", skip(parameters, pool, request))]
+pub async fn export_subscribers(
+    parameters: web::Query<ExportParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate(request.headers(), &pool, ApiScope::SubscribersRead).await {
+        return response;
+    }
+    let parameters = parameters.into_inner();
+    let status = match parameters.status.map(SubscriptionStatus::try_from).transpose() {
+        Ok(status) => status,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let format = parameters.format.unwrap_or_default();
+    let (content_type, filename) = match format {
+        ExportFormat::Csv => ("text/csv", "subscribers.csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "subscribers.ndjson"),
+    };
+    // The rows are written by a separate task so only `EXPORT_BUFFER` of them are ever in memory.
+    let (sender, receiver) = mpsc::channel::<Chunk>(EXPORT_BUFFER);
+    tokio::spawn(write_export(
+        pool.get_ref().clone(),
+        status,
+        parameters.list,
+        format,
+        sender,
+    ));
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename.to_owned())],
+        })
+        .streaming(body)
+}
+
+/// Streams every matching subscription into `sender` until the table or the client runs out.
+#[tracing::instrument(name = "Write subscriber export", skip(pool, sender))]
+async fn write_export(
+    pool: PgPool,
+    status: Option<SubscriptionStatus>,
+    list: Option<String>,
+    format: ExportFormat,
+    sender: mpsc::Sender<Chunk>,
+) {
+    if let ExportFormat::Csv = format {
+        let header = encode_csv_record(CSV_HEADER);
+        if sender.send(Ok(header)).await.is_err() {
+            return;
+        }
+    }
+    let mut rows = sqlx::query_as!(
+        ExportedSubscriber,
+        r#"
+            SELECT s.id, l.slug AS list, s.email, s.name, s.status, s.digest_frequency,
+                   s.consent_source, s.subscribed_at,
+                   ARRAY(
+                       SELECT tag FROM subscription_tags WHERE subscriber_id = s.id ORDER BY tag
+                   ) AS "tags!"
+            FROM subscriptions s
+            JOIN lists l ON l.list_id = s.list_id
+            WHERE ($1::text IS NULL OR s.status = $1)
+              AND ($2::text IS NULL OR l.slug = $2)
+            ORDER BY s.subscribed_at, s.import_position, s.id
+        "#,
+        status.map(|status| status.as_str()),
+        list.as_deref(),
+    )
+    .fetch(&pool);
+    loop {
+        let chunk = match rows.try_next().await {
+            Ok(Some(subscriber)) => Ok(encode(&subscriber, format)),
+            Ok(None) => return,
+            Err(e) => {
+                tracing::error!("Failed to fetch subscribers to export: {:?}", e);
+                Err(e)
+            }
+        };
+        let failed = chunk.is_err();
+        // Either the client went away or the response is already broken, nothing left to write.
+        if sender.send(chunk).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+fn encode(subscriber: &ExportedSubscriber, format: ExportFormat) -> web::Bytes {
+    match format {
+        ExportFormat::Csv => encode_csv_record([
+            subscriber.id.to_string().as_str(),
+            &subscriber.list,
+            &subscriber.email,
+            &subscriber.name,
+            &subscriber.status,
+            &subscriber.digest_frequency,
+            &subscriber.consent_source,
+            &subscriber.subscribed_at.to_rfc3339(),
+            &subscriber.tags.join(","),
+        ]),
+        ExportFormat::Ndjson => {
+            let mut line =
+                serde_json::to_vec(subscriber).expect("Failed to serialize an exported subscriber");
+            line.push(b'\n');
+            line.into()
+        }
+    }
+}
+
+fn encode_csv_record(record: [&str; 9]) -> web::Bytes {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(record)
+        .expect("Failed to write a CSV record into memory");
+    writer
+        .into_inner()
+        .expect("Failed to flush a CSV record into memory")
+        .into()
+}
@@ -0,0 +1,413 @@
+use std::collections::{HashMap, HashSet};
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::authenticate_admin;
+use crate::domain::{Subscriber, SubscriberName, SubscriptionStatus};
+use crate::email_client::ConfirmationTemplates;
+use crate::routes::{generate_subscription_token, resolve_list};
+
+const MAX_FILE_SIZE: usize = 5 * 1024 * 1024;
+/// Rows saved per statement, and per transaction.
+const CHUNK_SIZE: usize = 500;
+/// Every other form field is a short piece of text.
+const MAX_FIELD_SIZE: usize = 1024;
+
+/// The multipart form: a `file` with `email` and `name` columns, and optionally `locale`,
+/// plus the `consent_source` every row is recorded with.
+struct ImportForm {
+    file: Vec<u8>,
+    list: Option<String>,
+    consent_source: String,
+    /// For an audience that already confirmed with the previous tool.
+    skip_confirmation: bool,
+}
+
+struct CsvRow {
+    line: u64,
+    email: String,
+    name: String,
+    locale: Option<String>,
+}
+
+/// A row that passed validation, waiting for its chunk to be saved.
+struct NewSubscriber {
+    /// Where the row's entry sits in the report.
+    report_index: usize,
+    subscriber_id: Uuid,
+    subscriber: Subscriber,
+    /// The row's line in the file, which orders the rows of a chunk saved at the same instant.
+    import_position: i64,
+    locale: String,
+    subscription_token: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ImportReport {
+    accepted: usize,
+    rejected: usize,
+    rows: Vec<RowReport>,
+}
+
+#[derive(serde::Serialize)]
+pub struct RowReport {
+    line: u64,
+    email: Option<String>,
+    #[serde(flatten)]
+    outcome: RowOutcome,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum RowOutcome {
+    Accepted { subscriber_id: Uuid },
+    Rejected { reason: String },
+}
+
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(multipart, pool, confirmation_templates, request)
+)]
+pub async fn import_subscribers(
+    multipart: Multipart,
+    pool: web::Data<PgPool>,
+    confirmation_templates: web::Data<ConfirmationTemplates>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    let form = match read_form(multipart).await {
+        Ok(form) => form,
+        Err(response) => return response,
+    };
+    let rows = match parse_csv(&form.file) {
+        Ok(rows) => rows,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let list_id = match resolve_list(pool.get_ref(), form.list.as_deref()).await {
+        Ok(list_id) => list_id,
+        Err(response) => return response,
+    };
+    let emails: Vec<String> = rows
+        .iter()
+        .filter_map(|row| row.as_ref().ok())
+        .filter_map(|row| Subscriber::parse_email(&row.email).ok())
+        .map(|email| email.to_string())
+        .collect();
+    let existing = match get_existing_emails(&pool, list_id, &emails).await {
+        Ok(existing) => existing,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let status = if form.skip_confirmation {
+        SubscriptionStatus::Confirmed
+    } else {
+        SubscriptionStatus::PendingConfirmation
+    };
+
+    // Rows that pass validation are reported as accepted up front, and corrected once
+    // their chunk is saved. Confirmation emails are queued with their rows and sent by
+    // the confirmation worker, so even a long file is answered as soon as it is saved.
+    let mut report = Vec::with_capacity(rows.len());
+    let mut new_subscribers = Vec::new();
+    let mut first_seen_on: HashMap<String, u64> = HashMap::new();
+    for row in rows {
+        let row = match row {
+            Ok(row) => row,
+            Err(unreadable) => {
+                report.push(unreadable);
+                continue;
+            }
+        };
+        let outcome = match validate_row(&row, &existing, &mut first_seen_on) {
+            Ok(subscriber) => {
+                let subscriber_id = Uuid::new_v4();
+                new_subscribers.push(NewSubscriber {
+                    report_index: report.len(),
+                    subscriber_id,
+                    subscriber,
+                    import_position: row.line as i64,
+                    locale: confirmation_templates
+                        .negotiate_locale(row.locale.as_deref(), None)
+                        .to_owned(),
+                    subscription_token: (!form.skip_confirmation)
+                        .then(generate_subscription_token),
+                });
+                RowOutcome::Accepted { subscriber_id }
+            }
+            Err(reason) => RowOutcome::Rejected { reason },
+        };
+        report.push(RowReport {
+            line: row.line,
+            email: Some(row.email),
+            outcome,
+        });
+    }
+    for chunk in new_subscribers.chunks(CHUNK_SIZE) {
+        let reason = match save_chunk(&pool, chunk, list_id, status, &form.consent_source).await {
+            Ok(saved) => {
+                // e.g. an address that signed up through the form since we checked.
+                for new_subscriber in chunk
+                    .iter()
+                    .filter(|new_subscriber| !saved.contains(&new_subscriber.subscriber_id))
+                {
+                    report[new_subscriber.report_index].outcome = RowOutcome::Rejected {
+                        reason: "Already subscribed to this list".to_owned(),
+                    };
+                }
+                continue;
+            }
+            Err(_) => "Failed to save the subscriber",
+        };
+        for new_subscriber in chunk {
+            report[new_subscriber.report_index].outcome = RowOutcome::Rejected {
+                reason: reason.to_owned(),
+            };
+        }
+    }
+    let accepted = report
+        .iter()
+        .filter(|row| matches!(row.outcome, RowOutcome::Accepted { .. }))
+        .count();
+    HttpResponse::Ok().json(ImportReport {
+        accepted,
+        rejected: report.len() - accepted,
+        rows: report,
+    })
+}
+
+async fn read_form(mut multipart: Multipart) -> Result<ImportForm, HttpResponse> {
+    let mut file = None;
+    let mut list = None;
+    let mut consent_source = None;
+    let mut skip_confirmation = false;
+    while let Some(mut field) = multipart
+        .try_next()
+        .await
+        .map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?
+    {
+        let name = field.name().to_owned();
+        let limit = if name == "file" {
+            MAX_FILE_SIZE
+        } else {
+            MAX_FIELD_SIZE
+        };
+        let mut value = Vec::new();
+        while let Some(chunk) = field
+            .try_next()
+            .await
+            .map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?
+        {
+            if value.len() + chunk.len() > limit {
+                return Err(HttpResponse::PayloadTooLarge().body(format!("{} is too large", name)));
+            }
+            value.extend_from_slice(&chunk);
+        }
+        if name == "file" {
+            file = Some(value);
+            continue;
+        }
+        let value = String::from_utf8(value)
+            .map_err(|_| HttpResponse::BadRequest().body(format!("{} is not valid UTF8", name)))?;
+        let value = value.trim().to_owned();
+        match name.as_str() {
+            "list" => list = Some(value).filter(|list| !list.is_empty()),
+            "consent_source" => consent_source = Some(value).filter(|source| !source.is_empty()),
+            "skip_confirmation" => {
+                skip_confirmation = value.parse().map_err(|_| {
+                    HttpResponse::BadRequest().body("skip_confirmation must be true or false")
+                })?
+            }
+            _ => {}
+        }
+    }
+    Ok(ImportForm {
+        file: file.ok_or_else(|| HttpResponse::BadRequest().body("A CSV file is required"))?,
+        list,
+        consent_source: consent_source.ok_or_else(|| {
+            HttpResponse::BadRequest().body("Every import needs a consent_source")
+        })?,
+        skip_confirmation,
+    })
+}
+
+/// Rows that cannot even be read come back already rejected.
+fn parse_csv(file: &[u8]) -> Result<Vec<Result<CsvRow, RowReport>>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(file);
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(name))
+    };
+    let (Some(email), Some(name)) = (column("email"), column("name")) else {
+        return Err("The CSV needs an email and a name column".to_owned());
+    };
+    let locale = column("locale");
+    Ok(reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| RowReport {
+                line: e.position().map(|p| p.line()).unwrap_or_default(),
+                email: None,
+                outcome: RowOutcome::Rejected {
+                    reason: e.to_string(),
+                },
+            })?;
+            let get = |i: usize| record.get(i).unwrap_or_default().to_owned();
+            Ok(CsvRow {
+                line: record.position().map(|p| p.line()).unwrap_or_default(),
+                email: get(email),
+                name: get(name),
+                locale: locale.map(get).filter(|locale| !locale.is_empty()),
+            })
+        })
+        .collect())
+}
+
+/// `first_seen_on` maps every address accepted so far to its line, to catch duplicates in the file.
+fn validate_row(
+    row: &CsvRow,
+    existing: &HashSet<String>,
+    first_seen_on: &mut HashMap<String, u64>,
+) -> Result<Subscriber, String> {
+    let email = Subscriber::parse_email(&row.email)
+        .map_err(|e| format!("{} Not a valid email: {}", row.email, e))?;
+    let name = SubscriberName::parse(row.name.clone())?;
+    let key = email.to_string();
+    if existing.contains(&key) {
+        return Err("Already subscribed to this list".to_owned());
+    }
+    if let Some(line) = first_seen_on.get(&key) {
+        return Err(format!("Duplicate of line {}", line));
+    }
+    first_seen_on.insert(key, row.line);
+    Ok(Subscriber { email, name })
+}
+
+/// Saves a chunk of subscribers, with their confirmation tokens and queued emails, in a
+/// transaction of its own. Returns the ids of those saved, skipping any address that is
+/// already on the list.
+#[tracing::instrument(
+    name = "Save a chunk of imported subscribers",
+    skip(pool, chunk),
+    fields(n_rows = chunk.len())
+)]
+async fn save_chunk(
+    pool: &PgPool,
+    chunk: &[NewSubscriber],
+    list_id: Uuid,
+    status: SubscriptionStatus,
+    consent_source: &str,
+) -> Result<HashSet<Uuid>, sqlx::Error> {
+    let mut ids = Vec::with_capacity(chunk.len());
+    let mut emails = Vec::with_capacity(chunk.len());
+    let mut names = Vec::with_capacity(chunk.len());
+    let mut locales = Vec::with_capacity(chunk.len());
+    let mut import_positions = Vec::with_capacity(chunk.len());
+    for new_subscriber in chunk {
+        ids.push(new_subscriber.subscriber_id);
+        emails.push(new_subscriber.subscriber.email.to_string());
+        names.push(new_subscriber.subscriber.name.as_ref().to_owned());
+        locales.push(new_subscriber.locale.clone());
+        import_positions.push(new_subscriber.import_position);
+    }
+    let mut transaction = pool.begin().await?;
+    let saved: HashSet<Uuid> = sqlx::query_scalar!(
+        r#"
+            INSERT INTO subscriptions (id, list_id, email, name, subscribed_at, status, consent_source, locale, import_position)
+            SELECT t.id, $2, t.email, t.name, now(), $3, $4, t.locale, t.import_position
+            FROM UNNEST($1::uuid[], $5::text[], $6::text[], $7::text[], $8::bigint[])
+                AS t(id, email, name, locale, import_position)
+            ON CONFLICT (list_id, email) DO NOTHING
+            RETURNING id
+        "#,
+        &ids,
+        list_id,
+        status.as_str(),
+        consent_source,
+        &emails,
+        &names,
+        &locales,
+        &import_positions,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    .into_iter()
+    .collect();
+    let (subscriber_ids, subscription_tokens): (Vec<Uuid>, Vec<String>) = chunk
+        .iter()
+        .filter(|new_subscriber| saved.contains(&new_subscriber.subscriber_id))
+        .filter_map(|new_subscriber| {
+            let subscription_token = new_subscriber.subscription_token.clone()?;
+            Some((new_subscriber.subscriber_id, subscription_token))
+        })
+        .unzip();
+    if !subscriber_ids.is_empty() {
+        sqlx::query!(
+            r#"
+                INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+                SELECT * FROM UNNEST($1::text[], $2::uuid[])
+            "#,
+            &subscription_tokens,
+            &subscriber_ids,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        sqlx::query!(
+            r#"
+                INSERT INTO confirmation_email_queue (subscriber_id, subscription_token)
+                SELECT * FROM UNNEST($1::uuid[], $2::text[])
+            "#,
+            &subscriber_ids,
+            &subscription_tokens,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    }
+    transaction.commit().await?;
+    Ok(saved)
+}
+
+/// The addresses, among `emails`, already on the list.
+#[tracing::instrument(name = "Get existing subscriber emails", skip(pool, emails))]
+async fn get_existing_emails(
+    pool: &PgPool,
+    list_id: Uuid,
+    emails: &[String],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let rows = sqlx::query_scalar!(
+        r#"
+            SELECT email
+            FROM subscriptions
+            WHERE list_id = $1 AND email = ANY($2)
+        "#,
+        list_id,
+        emails,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(rows.into_iter().collect())
+}
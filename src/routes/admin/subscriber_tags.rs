@@ -0,0 +1,177 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::authenticate_admin;
+use crate::authentication::authenticate;
+use crate::domain::{ApiScope, SubscriberTag};
+
+#[derive(serde::Deserialize)]
+pub struct TagsData {
+    tags: Vec<SubscriberTag>,
+}
+
+#[tracing::instrument(name = "Get subscriber tags", skip(pool, request))]
+pub async fn get_subscriber_tags(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate(request.headers(), &pool, ApiScope::SubscribersRead).await {
+        return response;
+    }
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match fetch_tags(&mut transaction, *subscriber_id).await {
+        Ok(Some(tags)) => HttpResponse::Ok().json(tags),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Replace subscriber tags", skip(body, pool, request))]
+pub async fn replace_subscriber_tags(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<TagsData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match fetch_tags(&mut transaction, *subscriber_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    if remove_tags(&mut transaction, *subscriber_id, None)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if add_subscriber_tags(&mut transaction, *subscriber_id, &body.tags)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let tags = match fetch_tags(&mut transaction, *subscriber_id).await {
+        Ok(tags) => tags.unwrap_or_default(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().json(tags)
+}
+
+#[tracing::instrument(name = "Delete a subscriber tag", skip(pool, request))]
+pub async fn delete_subscriber_tag(
+    path: web::Path<(Uuid, String)>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    let (subscriber_id, tag) = path.into_inner();
+    let tag = match SubscriberTag::parse(tag) {
+        Ok(tag) => tag,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let response = match remove_tags(&mut transaction, subscriber_id, Some(&tag)).await {
+        Ok(0) => HttpResponse::NotFound().finish(),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    response
+}
+
+/// Adds `tags` on top of the ones the subscriber already has.
+#[tracing::instrument(name = "Add subscriber tags", skip(transaction))]
+pub async fn add_subscriber_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    tags: &[SubscriberTag],
+) -> Result<(), sqlx::Error> {
+    let tags: Vec<String> = tags.iter().map(|tag| tag.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"
+            INSERT INTO subscription_tags (subscriber_id, tag)
+            SELECT $1, tag FROM UNNEST($2::text[]) AS tag
+            ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        &tags,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// `None` if there is no such subscriber.
+#[tracing::instrument(name = "Fetch subscriber tags", skip(transaction))]
+async fn fetch_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<Vec<String>>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            SELECT ARRAY(
+                SELECT tag FROM subscription_tags WHERE subscriber_id = s.id ORDER BY tag
+            ) AS "tags!"
+            FROM subscriptions s
+            WHERE s.id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| r.tags))
+}
+
+/// Removes `tag`, or every tag when `None`, returning how many were removed.
+#[tracing::instrument(name = "Remove subscriber tags", skip(transaction))]
+async fn remove_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    tag: Option<&SubscriberTag>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            DELETE FROM subscription_tags
+            WHERE subscriber_id = $1 AND ($2::text IS NULL OR tag = $2)
+        "#,
+        subscriber_id,
+        tag.map(|tag| tag.as_ref()),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,308 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::authenticate_admin;
+use crate::authentication::authenticate;
+use crate::domain::{ApiScope, SubscriberCursor, SubscriberName, SubscriptionStatus};
+use crate::routes::{change_subscription_status, StatusChange};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// Every filter is optional, e.g. `?status=confirmed&email=gmail&subscribed_after=2024-01-01T00:00:00Z`.
+#[derive(serde::Deserialize)]
+pub struct SubscriberFilters {
+    status: Option<String>,
+    /// Matched case-insensitively anywhere in the address.
+    email: Option<String>,
+    list: Option<String>,
+    subscribed_after: Option<DateTime<Utc>>,
+    subscribed_before: Option<DateTime<Utc>>,
+    /// The `next_cursor` of the previous page.
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberEdit {
+    name: Option<String>,
+    status: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberDetails {
+    id: Uuid,
+    list: String,
+    email: String,
+    name: String,
+    status: String,
+    digest_frequency: String,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberPage {
+    subscribers: Vec<SubscriberDetails>,
+    /// Missing on the last page.
+    next_cursor: Option<String>,
+}
+
+#[tracing::instrument(name = "List subscribers", skip(filters, pool, request))]
+pub async fn list_subscribers(
+    filters: web::Query<SubscriberFilters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate(request.headers(), &pool, ApiScope::SubscribersRead).await {
+        return response;
+    }
+    let status = match filters
+        .status
+        .clone()
+        .map(SubscriptionStatus::try_from)
+        .transpose()
+    {
+        Ok(status) => status,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let cursor = match filters
+        .cursor
+        .as_deref()
+        .map(SubscriberCursor::parse)
+        .transpose()
+    {
+        Ok(cursor) => cursor,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let limit = filters.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return HttpResponse::BadRequest().body(format!(
+            "Expected a limit between 1 and {}",
+            MAX_PAGE_SIZE
+        ));
+    }
+    // One extra row tells whether there is a page after this one.
+    let mut subscribers =
+        match get_subscribers(&pool, &filters, status, cursor.as_ref(), limit + 1).await {
+            Ok(subscribers) => subscribers,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+    let next_cursor = if subscribers.len() as i64 > limit {
+        subscribers.truncate(limit as usize);
+        subscribers.last().map(|last| {
+            SubscriberCursor {
+                subscribed_at: last.subscribed_at,
+                subscriber_id: last.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+    HttpResponse::Ok().json(SubscriberPage {
+        subscribers,
+        next_cursor,
+    })
+}
+
+#[tracing::instrument(name = "Get a subscriber", skip(pool, request))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate(request.headers(), &pool, ApiScope::SubscribersRead).await {
+        return response;
+    }
+    match fetch_subscriber(pool.get_ref(), *subscriber_id).await {
+        Ok(Some(subscriber)) => HttpResponse::Ok().json(subscriber),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Edit a subscriber", skip(body, pool, request))]
+pub async fn edit_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<SubscriberEdit>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    let body = body.into_inner();
+    let name = match body.name.map(SubscriberName::parse).transpose() {
+        Ok(name) => name,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let status = match body.status.map(SubscriptionStatus::try_from).transpose() {
+        Ok(status) => status,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if let Some(name) = name {
+        match update_name(&mut transaction, *subscriber_id, &name).await {
+            Ok(true) => {}
+            Ok(false) => return HttpResponse::NotFound().finish(),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+    // Admins get no shortcut around the state machine, e.g. a complaint is final.
+    if let Some(status) = status {
+        match change_subscription_status(&mut transaction, *subscriber_id, status).await {
+            Ok(StatusChange::Changed | StatusChange::Unchanged) => {}
+            Ok(StatusChange::Rejected(current)) => {
+                return HttpResponse::Conflict().body(format!(
+                    "Cannot move a subscriber from {} to {}",
+                    current.as_str(),
+                    status.as_str()
+                ))
+            }
+            Ok(StatusChange::NotFound) => return HttpResponse::NotFound().finish(),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+    let subscriber = match fetch_subscriber(&mut *transaction, *subscriber_id).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().json(subscriber)
+}
+
+#[tracing::instrument(name = "Delete a subscriber", skip(pool, request))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_admin(&request, &pool).await {
+        return response;
+    }
+    match remove_subscriber(&pool, *subscriber_id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Escapes `%`, `_` and `\` so `s` only ever matches itself inside a LIKE pattern.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('%', r"\%")
+        .replace('_', r"\_")
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(pool, filters))]
+async fn get_subscribers(
+    pool: &PgPool,
+    filters: &SubscriberFilters,
+    status: Option<SubscriptionStatus>,
+    cursor: Option<&SubscriberCursor>,
+    limit: i64,
+) -> Result<Vec<SubscriberDetails>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+            SELECT s.id, l.slug AS list, s.email, s.name, s.status, s.digest_frequency,
+                   s.subscribed_at,
+                   ARRAY(
+                       SELECT tag FROM subscription_tags WHERE subscriber_id = s.id ORDER BY tag
+                   ) AS "tags!"
+            FROM subscriptions s
+            JOIN lists l ON l.list_id = s.list_id
+            WHERE ($1::text IS NULL OR s.status = $1)
+              AND ($2::text IS NULL OR s.email ILIKE '%' || $2 || '%')
+              AND ($3::text IS NULL OR l.slug = $3)
+              AND ($4::timestamptz IS NULL OR s.subscribed_at >= $4)
+              AND ($5::timestamptz IS NULL OR s.subscribed_at < $5)
+              AND ($6::timestamptz IS NULL OR (s.subscribed_at, s.id) < ($6, $7))
+            ORDER BY s.subscribed_at DESC, s.id DESC
+            LIMIT $8
+        "#,
+        status.map(|status| status.as_str()),
+        filters.email.as_deref().map(escape_like),
+        filters.list.as_deref(),
+        filters.subscribed_after,
+        filters.subscribed_before,
+        cursor.map(|cursor| cursor.subscribed_at),
+        cursor.map(|cursor| cursor.subscriber_id),
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Fetch a subscriber", skip(executor))]
+async fn fetch_subscriber(
+    executor: impl Executor<'_, Database = Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDetails>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+            SELECT s.id, l.slug AS list, s.email, s.name, s.status, s.digest_frequency,
+                   s.subscribed_at,
+                   ARRAY(
+                       SELECT tag FROM subscription_tags WHERE subscriber_id = s.id ORDER BY tag
+                   ) AS "tags!"
+            FROM subscriptions s
+            JOIN lists l ON l.list_id = s.list_id
+            WHERE s.id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Update subscriber name", skip(transaction))]
+async fn update_name(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    name: &SubscriberName,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2 WHERE id = $1"#,
+        subscriber_id,
+        name.as_ref(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Tokens, tags and queued deliveries go with the subscriber, see the `ON DELETE CASCADE`s.
+#[tracing::instrument(name = "Remove a subscriber", skip(pool))]
+async fn remove_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(result.rows_affected() == 1)
+}
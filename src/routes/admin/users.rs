@@ -0,0 +1,237 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::authenticate_superuser;
+use crate::authentication::hash_password;
+use crate::domain::AdminPassword;
+use crate::session::TypedSession;
+
+#[derive(serde::Deserialize)]
+pub struct NewUserData {
+    username: String,
+    password: Secret<String>,
+    #[serde(default)]
+    is_superuser: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UserEdit {
+    disabled: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct AdminUser {
+    user_id: Uuid,
+    username: String,
+    is_superuser: bool,
+    /// Missing while the user can log in.
+    disabled_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "List admin users", skip(pool, request))]
+pub async fn list_users(pool: web::Data<PgPool>, request: HttpRequest) -> HttpResponse {
+    if let Err(response) = authenticate_superuser(&request, &pool).await {
+        return response;
+    }
+    match get_users(&pool).await {
+        Ok(users) => HttpResponse::Ok().json(users),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(
+    name = "Create an admin user",
+    skip(body, pool, request),
+    fields(username = %body.username)
+)]
+pub async fn create_user(
+    body: web::Json<NewUserData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = authenticate_superuser(&request, &pool).await {
+        return response;
+    }
+    let body = body.into_inner();
+    let username = match parse_username(&body.username) {
+        Ok(username) => username,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let password = match AdminPassword::parse(body.password) {
+        Ok(password) => password,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let password_hash = match hash_password(password).await {
+        Ok(password_hash) => password_hash,
+        Err(e) => {
+            tracing::error!("Failed to hash password: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    match insert_user(&pool, username, &password_hash, body.is_superuser).await {
+        Ok(Some(user)) => HttpResponse::Created().json(user),
+        Ok(None) => HttpResponse::Conflict().body("The username is already taken"),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Edit an admin user", skip(body, pool, request))]
+pub async fn edit_user(
+    user_id: web::Path<Uuid>,
+    body: web::Json<UserEdit>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let current_user_id = match authenticate_superuser(&request, &pool).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    // Keeps at least one superuser around, the one making the request.
+    if body.disabled && *user_id == current_user_id {
+        return HttpResponse::BadRequest().body("You cannot disable your own account");
+    }
+    let user = match set_disabled(&pool, *user_id, body.disabled).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if body.disabled
+        && TypedSession::log_out_everywhere(*user_id, &pool)
+            .await
+            .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().json(user)
+}
+
+#[tracing::instrument(name = "Delete an admin user", skip(pool, request))]
+pub async fn delete_user(
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let current_user_id = match authenticate_superuser(&request, &pool).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    if *user_id == current_user_id {
+        return HttpResponse::BadRequest().body("You cannot delete your own account");
+    }
+    match remove_user(&pool, *user_id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    if TypedSession::log_out_everywhere(*user_id, &pool)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::NoContent().finish()
+}
+
+/// Basic auth splits the credentials on the first `:`, so a username can't contain one.
+fn parse_username(s: &str) -> Result<&str, &'static str> {
+    let username = s.trim();
+    if username.is_empty() {
+        return Err("A user needs a username");
+    }
+    if username.chars().count() > 256 {
+        return Err("The username must be at most 256 characters long");
+    }
+    if username.contains(':') {
+        return Err("The username cannot contain ':'");
+    }
+    Ok(username)
+}
+
+#[tracing::instrument(name = "Get admin users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<AdminUser>, sqlx::Error> {
+    sqlx::query_as!(
+        AdminUser,
+        r#"
+            SELECT user_id, username, is_superuser, disabled_at
+            FROM users
+            ORDER BY username
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(
+    name = "Saving new admin user in the database",
+    skip(pool, password_hash)
+)]
+async fn insert_user(
+    pool: &PgPool,
+    username: &str,
+    password_hash: &Secret<String>,
+    is_superuser: bool,
+) -> Result<Option<AdminUser>, sqlx::Error> {
+    sqlx::query_as!(
+        AdminUser,
+        r#"
+            INSERT INTO users (user_id, username, password_hash, is_superuser)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (username) DO NOTHING
+            RETURNING user_id, username, is_superuser, disabled_at
+        "#,
+        Uuid::new_v4(),
+        username,
+        password_hash.expose_secret(),
+        is_superuser,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Disable or enable an admin user", skip(pool))]
+async fn set_disabled(
+    pool: &PgPool,
+    user_id: Uuid,
+    disabled: bool,
+) -> Result<Option<AdminUser>, sqlx::Error> {
+    sqlx::query_as!(
+        AdminUser,
+        r#"
+            UPDATE users
+            SET disabled_at = CASE WHEN $2 THEN COALESCE(disabled_at, now()) END
+            WHERE user_id = $1
+            RETURNING user_id, username, is_superuser, disabled_at
+        "#,
+        user_id,
+        disabled,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Delete an admin user from the database", skip(pool))]
+async fn remove_user(pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(r#"DELETE FROM users WHERE user_id = $1"#, user_id)
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(result.rows_affected() == 1)
+}
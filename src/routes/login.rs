@@ -0,0 +1,107 @@
+use std::fmt::Write;
+
+use actix_web::http::header::{ContentType, LOCATION};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
+use minijinja::HtmlEscape;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use crate::authentication::{validate_credentials, Credentials};
+use crate::session::TypedSession;
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: Secret<String>,
+}
+
+/// Redirects a form submission, the browser follows up with a GET.
+pub fn see_other(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish()
+}
+
+/// One `<p>` per flash message, e.g. `<p class="error">Authentication failed</p>`.
+pub fn render_flash_messages(flash_messages: &IncomingFlashMessages) -> String {
+    let mut html = String::new();
+    for message in flash_messages.iter() {
+        let class = match message.level() {
+            Level::Error => "error",
+            _ => "info",
+        };
+        writeln!(
+            html,
+            r#"<p class="{}"><i>{}</i></p>"#,
+            class,
+            HtmlEscape(message.content())
+        )
+        .unwrap();
+    }
+    html
+}
+
+pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    {}
+    <form action="/login" method="post">
+        <label>Username
+            <input type="text" placeholder="Enter Username" name="username">
+        </label>
+        <label>Password
+            <input type="password" placeholder="Enter Password" name="password">
+        </label>
+        <button type="submit">Login</button>
+    </form>
+</body>
+</html>"#,
+            render_flash_messages(&flash_messages)
+        ))
+}
+
+#[tracing::instrument(
+    name = "Log in",
+    skip(form, pool, session),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn login(
+    form: web::Form<LoginFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> HttpResponse {
+    let form = form.into_inner();
+    tracing::Span::current().record("username", tracing::field::display(&form.username));
+    let credentials = Credentials {
+        username: form.username,
+        password: form.password,
+    };
+    let user_id = match validate_credentials(credentials, &pool).await {
+        Ok(user_id) => user_id,
+        Err(mut http_response) => {
+            let response = http_response.finish();
+            if response.status() != StatusCode::UNAUTHORIZED {
+                return response;
+            }
+            FlashMessage::error("Authentication failed").send();
+            return see_other("/login");
+        }
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    session.renew();
+    if let Err(e) = session.insert_user_id(user_id) {
+        tracing::error!("Failed to store the user id in the session: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    see_other("/admin/dashboard")
+}
@@ -1,8 +1,18 @@
 mod health_check;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
+mod subscriptions_unsubscribe;
 mod newsletter;
+mod newsletter_archive;
+mod admin;
+mod login;
 pub use health_check::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_unsubscribe::*;
 pub use newsletter::*;
+pub use newsletter_archive::*;
+pub use admin::*;
+pub use login::*;
@@ -1,22 +1,21 @@
-use actix_web::http::header;
-use actix_web::http::header::HeaderValue;
-use actix_web::HttpResponseBuilder;
-use actix_web::{http::header::HeaderMap, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{http::header::HeaderMap, web, HttpRequest, HttpResponse};
 use anyhow::Context;
-use argon2::password_hash::PasswordVerifier;
-use argon2::{Argon2, PasswordHash};
-use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
 use lettre::Address;
-use secrecy::ExposeSecret;
-use secrecy::Secret;
-use sqlx::PgPool;
+use sqlx::types::Json;
+use sqlx::{Executor, PgPool, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
 
-use crate::{
-    domain::{Subscriber, SubscriberName},
-    email_client::EmailClient,
+use crate::authentication::authenticate;
+use crate::domain::{
+    ApiScope, IssueTemplate, Segment, Subscriber, SubscriberName, TemplateError, TemplateFormat,
 };
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::routes::resolve_list;
 
+#[derive(sqlx::FromRow)]
 struct Row {
+    id: Uuid,
     email: String,
     name: String,
 }
@@ -28,191 +27,319 @@ impl TryInto<Subscriber> for Row {
         Ok(Subscriber { email, name })
     }
 }
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
-async fn get_confirmed_subscribers(pool: &PgPool) -> Result<Vec<Subscriber>, sqlx::Error> {
-    let rows: Vec<Row> = sqlx::query_as!(
-        Row,
-        r#"
-            SELECT email, name
-            FROM subscriptions
-            WHERE status = 'confirmed'
-        "#,
-    )
-    .fetch_all(pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to get all confirmed subscriber: {}", e);
-        e
-    })?;
-    let confirmed_subscriber: Vec<Subscriber> = rows
+/// Confirmed subscribers of a list, narrowed down to `segment` by the database itself.
+#[tracing::instrument(name = "Get confirmed subscribers", skip(executor))]
+pub async fn get_confirmed_subscribers(
+    executor: impl Executor<'_, Database = Postgres>,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        "SELECT s.id, s.email, s.name FROM subscriptions s WHERE s.status = 'confirmed' AND s.list_id = ",
+    );
+    query.push_bind(list_id);
+    if let Some(segment) = segment {
+        query.push(" AND ");
+        push_segment(&mut query, segment);
+    }
+    let rows: Vec<Row> = query
+        .build_query_as()
+        .fetch_all(executor)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to get all confirmed subscriber: {}", e);
+            e
+        })?;
+    let confirmed_subscriber_ids: Vec<Uuid> = rows
         .into_iter()
         .filter_map(|item| {
+            let id = item.id;
             let x: Result<Subscriber, _> = item.try_into();
             match x {
-                Ok(subscriber) => Some(subscriber),
-                Err(_) => None,
+                Ok(_) => Some(id),
+                Err(e) => {
+                    tracing::warn!(
+                        "Skipping a confirmed subscriber with invalid details: {}",
+                        e
+                    );
+                    None
+                }
             }
         })
         .collect();
-    Ok(confirmed_subscriber)
+    Ok(confirmed_subscriber_ids)
+}
+
+fn push_segment(query: &mut QueryBuilder<'_, Postgres>, segment: &Segment) {
+    match segment {
+        Segment::Tag(tag) => {
+            query.push(
+                "EXISTS (SELECT 1 FROM subscription_tags t WHERE t.subscriber_id = s.id AND t.tag = ",
+            );
+            query.push_bind(tag.as_ref().to_owned());
+            query.push(")");
+        }
+        Segment::SubscribedAfter(subscribed_after) => {
+            query.push("s.subscribed_at > ");
+            query.push_bind(*subscribed_after);
+        }
+        Segment::And(segments) if segments.is_empty() => {
+            query.push("TRUE");
+        }
+        Segment::Or(segments) if segments.is_empty() => {
+            query.push("FALSE");
+        }
+        Segment::And(segments) | Segment::Or(segments) => {
+            let operator = match segment {
+                Segment::And(_) => " AND ",
+                _ => " OR ",
+            };
+            query.push("(");
+            for (i, segment) in segments.iter().enumerate() {
+                if i > 0 {
+                    query.push(operator);
+                }
+                push_segment(query, segment);
+            }
+            query.push(")");
+        }
+        Segment::Not(segment) => {
+            query.push("NOT (");
+            push_segment(query, segment);
+            query.push(")");
+        }
+    }
 }
 
 #[derive(serde::Deserialize)]
 pub struct BodyData {
     subject: String,
-    content: String,
+    #[serde(alias = "content")]
+    html_content: String,
+    text_content: Option<String>,
+    /// Holds the issue back until then, a past timestamp publishes right away.
+    send_at: Option<DateTime<Utc>>,
+    /// Slug of the list to send to, the default list if missing.
+    list: Option<String>,
+    /// Narrows the list down, everyone confirmed on it if missing.
+    segment: Option<Segment>,
+}
+
+#[derive(serde::Serialize)]
+pub struct AcceptedIssue {
+    pub newsletter_issue_id: Uuid,
+    pub send_at: Option<DateTime<Utc>>,
 }
 
-fn get_unauthorized_response() -> HttpResponseBuilder {
-    let mut response = HttpResponse::Unauthorized();
-    let header_value = HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
-    response.insert_header((header::WWW_AUTHENTICATE, header_value));
-    response
+#[derive(serde::Serialize)]
+struct InvalidTemplate<'a> {
+    field: &'a str,
+    #[serde(flatten)]
+    error: TemplateError,
 }
 
-#[tracing::instrument(name = "Publish a newsletter", skip(body, pool, email_client), fields(username=tracing::field::Empty, user_id=tracing::field::Empty))]
+#[tracing::instrument(name = "Publish a newsletter", skip(body, pool, request), fields(username=tracing::field::Empty, user_id=tracing::field::Empty))]
 pub async fn publish_newsletter(
     body: web::Json<BodyData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     request: HttpRequest,
-) -> impl Responder {
-    let credentials = match basic_authentication(request.headers()) {
-        Ok(credentials) => credentials,
+) -> HttpResponse {
+    let user_id = match authenticate(request.headers(), &pool, ApiScope::NewsletterPublish).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let idempotency_key = match get_idempotency_key(request.headers()) {
+        Ok(idempotency_key) => idempotency_key,
         Err(e) => {
-            tracing::error!("Failed to authorize: {}", e);
-            return get_unauthorized_response();
+            tracing::error!("Invalid idempotency key: {}", e);
+            return HttpResponse::BadRequest().finish();
         }
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    publish_issue(&pool, user_id, idempotency_key, &body).await
+}
 
-    let user_id = match validate_credentials(credentials, &pool).await {
-        Ok(user_id) => user_id,
-        Err(http_response) => {
-            tracing::error!("Failed to validate credentials");
-            return http_response;
-        }
+/// Stores the issue and fans it out, or schedules it, on behalf of an already authenticated `user_id`.
+#[tracing::instrument(name = "Publish an issue", skip(pool, idempotency_key, body))]
+pub async fn publish_issue(
+    pool: &PgPool,
+    user_id: Uuid,
+    idempotency_key: Option<IdempotencyKey>,
+    body: &BodyData,
+) -> HttpResponse {
+    let text_content = plain_text_content(&body.html_content, body.text_content.as_deref());
+    if let Err(response) = validate_templates(&body.html_content, &text_content) {
+        return response;
+    }
+    let list_id = match resolve_list(pool, body.list.as_deref()).await {
+        Ok(list_id) => list_id,
+        Err(response) => return response,
+    };
+    let mut transaction = match &idempotency_key {
+        Some(idempotency_key) => match try_processing(pool, idempotency_key, user_id).await {
+            Ok(NextAction::StartProcessing(transaction)) => transaction,
+            Ok(NextAction::ReturnSavedResponse(saved_response)) => return saved_response,
+            Err(e) => {
+                tracing::error!("Failed to process idempotency key: {:?}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        },
+        None => match pool.begin().await {
+            Ok(transaction) => transaction,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
     };
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
 
-    let subscribers = match get_confirmed_subscribers(&pool).await {
-        Ok(subscriber) => subscriber,
-        Err(_) => return HttpResponse::InternalServerError(),
+    let send_at = body.send_at.filter(|send_at| *send_at > Utc::now());
+    let newsletter_issue_id = match insert_newsletter_issue(
+        &mut transaction,
+        list_id,
+        body.segment.as_ref(),
+        &body.subject,
+        &body.html_content,
+        &text_content,
+        send_at,
+    )
+    .await
+    {
+        Ok(newsletter_issue_id) => newsletter_issue_id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
     };
-    for subscriber in subscribers {
-        email_client
-            .send_email(
-                subscriber.name.as_ref().to_owned(),
-                subscriber.email,
-                &body.subject,
-                &body.content,
-            )
+    // Scheduled issues are fanned out by the scheduler once they are due.
+    if send_at.is_none() {
+        let subscriber_ids =
+            match get_confirmed_subscribers(pool, list_id, body.segment.as_ref()).await {
+                Ok(subscriber_ids) => subscriber_ids,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+        if enqueue_delivery_tasks(&mut transaction, newsletter_issue_id, &subscriber_ids)
             .await
-            .expect("Failed to send email");
+            .is_err()
+        {
+            return HttpResponse::InternalServerError().finish();
+        }
     }
-    HttpResponse::Ok()
-}
 
-struct Credentials {
-    username: String,
-    password: Secret<String>,
+    let response = HttpResponse::Accepted().json(AcceptedIssue {
+        newsletter_issue_id,
+        send_at,
+    });
+    match idempotency_key {
+        Some(idempotency_key) => {
+            match save_response(transaction, &idempotency_key, user_id, response).await {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::error!("Failed to save response: {:?}", e);
+                    HttpResponse::InternalServerError().finish()
+                }
+            }
+        }
+        None => {
+            if transaction.commit().await.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            response
+        }
+    }
 }
-fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
-    let header_value = headers
-        .get("Authorization")
-        .context("The 'Authorization' header was missing")?
-        .to_str()
-        .context("The 'Authorization' header was not a valid UTF8 string")?;
-    let base64encoded_segment = header_value
-        .strip_prefix("Basic ")
-        .context("The authorization scheme was not 'Basic'.")?;
-    let decoded_bytes = general_purpose::STANDARD
-        .decode(base64encoded_segment)
-        .context("Failed to base64-decode 'Basic' credentials.")?;
-    let decoded_credentials = String::from_utf8(decoded_bytes)
-        .context("The decoded credential string is not valid UTF8.")?;
-    let mut credentials = decoded_credentials.splitn(2, ':');
-    let username = credentials
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
-        .to_string();
-    let password = credentials
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
-        .to_string();
-    Ok(Credentials {
-        username,
-        password: Secret::new(password),
-    })
+
+#[tracing::instrument(
+    name = "Insert a newsletter issue",
+    skip(transaction, title, html_content, text_content)
+)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+    send_at: Option<DateTime<Utc>>,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let query = sqlx::query!(
+        r#"INSERT INTO newsletter_issues (newsletter_issue_id, list_id, segment, title, html_content, text_content, published_at, send_at, status)
+                    VALUES ($1, $2, $3, $4, $5, $6,
+                            CASE WHEN $7::timestamptz IS NULL THEN now() END,
+                            $7,
+                            CASE WHEN $7::timestamptz IS NULL THEN 'published' ELSE 'scheduled' END)"#,
+        newsletter_issue_id,
+        list_id,
+        segment.map(Json) as _,
+        title,
+        html_content,
+        text_content,
+        send_at,
+    );
+    transaction.execute(query).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(newsletter_issue_id)
 }
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
-async fn validate_credentials(
-    credentials: Credentials,
-    pool: &PgPool,
-) -> Result<uuid::Uuid, HttpResponseBuilder> {
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
+
+/// Subscribers on a daily or weekly digest get a `digest` task instead, held back until
+/// the start of the next UTC day or ISO week so the digest worker can batch it with the rest.
+#[tracing::instrument(name = "Enqueue delivery tasks", skip(transaction, subscriber_ids))]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    subscriber_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id, status, execute_after)
+                    SELECT $1, s.id,
+                           CASE WHEN s.digest_frequency = 'immediate' THEN 'pending' ELSE 'digest' END,
+                           CASE s.digest_frequency
+                               WHEN 'daily' THEN date_trunc('day', now(), 'UTC') + interval '1 day'
+                               WHEN 'weekly' THEN date_trunc('week', now(), 'UTC') + interval '1 week'
+                               ELSE now()
+                           END
+                    FROM subscriptions s
+                    WHERE s.id = ANY($2::uuid[])"#,
+        newsletter_issue_id,
+        subscriber_ids,
     );
-    let mut user_id = None;
-    if let Some((stored_user_id, strored_expected_password_hash)) =
-        get_stored_credentials(&credentials.username, pool)
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to get stored credentials: {}", e);
-                HttpResponse::InternalServerError()
-            })?
-    {
-        user_id = Some(stored_user_id);
-        expected_password_hash = strored_expected_password_hash;
-    }
-    tokio::task::spawn_blocking(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
-    })
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to start blocking task: {}", e);
-        HttpResponse::InternalServerError()
-    })?
-    .map_err(|e| {
-        tracing::error!("Failed to validate password: {}", e);
-        get_unauthorized_response()
+    transaction.execute(query).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
     })?;
-    user_id.ok_or_else(|| get_unauthorized_response())
+    Ok(())
 }
-#[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
-async fn get_stored_credentials(
-    username: &str,
-    pool: &PgPool,
-) -> Result<Option<(uuid::Uuid, Secret<String>)>, anyhow::Error> {
-    let row = sqlx::query!(
-        r#"
-            SELECT user_id, password_hash
-            FROM users
-            WHERE username = $1
-        "#,
-        username,
-    )
-    .fetch_optional(pool)
-    .await
-    .context("Failed to perform a query to retrieve stored credentials.")?
-    .map(|row| (row.user_id, Secret::new(row.password_hash)));
-    Ok(row)
+
+/// The plain-text part as given, or converted from the HTML one when it is missing.
+pub fn plain_text_content(html_content: &str, text_content: Option<&str>) -> String {
+    // A form left blank sends an empty string rather than leaving the field out.
+    match text_content.filter(|t| !t.trim().is_empty()) {
+        Some(text_content) => text_content.to_owned(),
+        None => html2text::from_read(html_content.as_bytes(), 80),
+    }
 }
 
-#[tracing::instrument(
-    name = "Verify password hash",
-    skip(expected_password_hash, password_candidate)
-)]
-fn verify_password_hash(
-    expected_password_hash: Secret<String>,
-    password_candidate: Secret<String>,
-) -> Result<(), argon2::password_hash::Error> {
-    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())?;
-    Argon2::default().verify_password(
-        password_candidate.expose_secret().as_bytes(),
-        &expected_password_hash,
-    )
+/// Issues are rendered per subscriber later on, so a broken template has to be caught here.
+pub fn validate_templates(html_content: &str, text_content: &str) -> Result<(), HttpResponse> {
+    for (field, source, format) in [
+        ("html_content", html_content, TemplateFormat::Html),
+        ("text_content", text_content, TemplateFormat::Text),
+    ] {
+        if let Err(error) = IssueTemplate::parse(source.to_owned(), format) {
+            tracing::warn!("Rejected a newsletter with an invalid {}: {}", field, error);
+            return Err(HttpResponse::BadRequest().json(InvalidTemplate { field, error }));
+        }
+    }
+    Ok(())
+}
+
+fn get_idempotency_key(headers: &HeaderMap) -> Result<Option<IdempotencyKey>, anyhow::Error> {
+    let Some(header_value) = headers.get("Idempotency-Key") else {
+        return Ok(None);
+    };
+    let idempotency_key = header_value
+        .to_str()
+        .context("The 'Idempotency-Key' header was not a valid UTF8 string")?
+        .to_string();
+    IdempotencyKey::parse(idempotency_key)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!(e))
 }
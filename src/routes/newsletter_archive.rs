@@ -0,0 +1,72 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{IssueTemplate, TemplateFormat};
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+
+struct ArchivedIssue {
+    title: String,
+    html_content: String,
+}
+
+#[tracing::instrument(name = "Show an archived newsletter issue", skip(pool, base_url))]
+pub async fn get_archived_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue = match get_archived_issue_content(&pool, newsletter_issue_id).await {
+        Ok(Some(issue)) => issue,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let archive_link = EmailClient::get_archive_link(&base_url.0, newsletter_issue_id);
+    let html_content = match IssueTemplate::parse(issue.html_content, TemplateFormat::Html)
+        .and_then(|template| template.render_for_archive(&archive_link.0))
+    {
+        Ok(html_content) => html_content,
+        Err(e) => {
+            tracing::error!("Failed to render archived issue: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            "<!doctype html><html><head><title>{}</title></head><body>{}</body></html>",
+            html_escape(&issue.title),
+            html_content
+        ))
+}
+
+pub(crate) fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[tracing::instrument(name = "Get archived newsletter issue", skip(pool))]
+async fn get_archived_issue_content(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<ArchivedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+            SELECT title, html_content
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1 AND status = 'published'
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
@@ -1,8 +1,12 @@
-use crate::{domain::Subscriber, email_client::EmailClient, startup::ApplicationBaseUrl};
-use actix_web::{web, HttpResponse, Responder};
+use crate::{
+    domain::{PreferencesToken, Subscriber, SubscriberTag, SubscriptionStatus},
+    email_client::{ConfirmationTemplates, EmailClient},
+    routes::{add_subscriber_tags, resolve_list},
+    startup::{ApplicationBaseUrl, HmacSecret},
+};
+use actix_web::{http::header::ACCEPT_LANGUAGE, web, HttpRequest, HttpResponse};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use reqwest::StatusCode;
 use sqlx::PgPool;
 use sqlx::{Executor, Postgres, Transaction};
 use uuid::Uuid;
@@ -11,9 +15,17 @@ use uuid::Uuid;
 pub struct FormData {
     pub email: String,
     pub name: String,
+    pub locale: Option<String>,
+    /// Slug of the list to join, the default list if missing.
+    pub list: Option<String>,
+    /// Comma separated, e.g. `rust,beginner`.
+    pub tags: Option<String>,
 }
 
-fn generate_subscription_token() -> String {
+/// Where the consent of subscribers who signed up themselves comes from.
+const SIGNUP_FORM_CONSENT: &str = "signup_form";
+
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -23,7 +35,7 @@ fn generate_subscription_token() -> String {
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, db_pool, email_client, base_url),
+    skip(form, db_pool, email_client, base_url, hmac_secret, confirmation_templates, request),
     fields(subscriber_email=%form.name,
            subscriber_name=%form.name)
 )]
@@ -32,79 +44,307 @@ pub async fn subscribe(
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> impl Responder {
+    hmac_secret: web::Data<HmacSecret>,
+    confirmation_templates: web::Data<ConfirmationTemplates>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let accept_language = request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|header| header.to_str().ok());
+    let requested_locale = form.locale.clone();
+    let list_slug = form.list.clone();
+    let tags: Result<Vec<SubscriberTag>, _> = form
+        .tags
+        .iter()
+        .flat_map(|tags| tags.split(','))
+        .filter(|tag| !tag.trim().is_empty())
+        .map(|tag| SubscriberTag::parse(tag.to_owned()))
+        .collect();
+    let Ok(tags) = tags else {
+        return HttpResponse::BadRequest().finish();
+    };
     let new_subscriber = match form.0.try_into() {
         Ok(subscriber) => subscriber,
         Err(_) => {
-            return HttpResponse::BadRequest();
+            return HttpResponse::BadRequest().finish();
         }
     };
 
     let mut transaction = match db_pool.begin().await {
         Ok(transaction) => transaction,
-        Err(_) => return HttpResponse::InternalServerError(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let list_id = match resolve_list(&mut *transaction, list_slug.as_deref()).await {
+        Ok(list_id) => list_id,
+        Err(response) => return response,
     };
 
-    let subscriber_id = match insert_subscriber(&new_subscriber, &mut transaction).await {
-        Ok(subscriber_id) => subscriber_id,
-        Err(_) => return HttpResponse::InternalServerError(),
+    let locale = confirmation_templates
+        .negotiate_locale(requested_locale.as_deref(), accept_language)
+        .to_owned();
+    let inserted = match insert_subscriber(
+        &new_subscriber,
+        list_id,
+        SubscriptionStatus::PendingConfirmation,
+        SIGNUP_FORM_CONSENT,
+        &locale,
+        &mut transaction,
+    )
+    .await
+    {
+        Ok(inserted) => inserted,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let (subscriber_id, locale) = match inserted {
+        Some(subscriber_id) => (subscriber_id, locale),
+        // Known address, possibly inserted by a concurrent request for the same one:
+        // (re)enter pending confirmation and mail a fresh token.
+        None => match get_existing_subscriber(&new_subscriber, list_id, &mut transaction).await {
+            Ok(Some((subscriber_id, stored_locale))) => {
+                match change_subscription_status(
+                    &mut transaction,
+                    subscriber_id,
+                    SubscriptionStatus::PendingConfirmation,
+                )
+                .await
+                {
+                    Ok(StatusChange::Changed | StatusChange::Unchanged) => {}
+                    // Nothing to confirm; answer as if it were new so the endpoint doesn't leak who is subscribed.
+                    Ok(StatusChange::Rejected(_) | StatusChange::NotFound) => {
+                        return HttpResponse::Ok().finish()
+                    }
+                    Err(_) => return HttpResponse::InternalServerError().finish(),
+                }
+                // A locale picked on the form replaces the stored one, the browser's doesn't.
+                let locale = confirmation_templates
+                    .negotiate_locale(
+                        requested_locale.as_deref().or(stored_locale.as_deref()),
+                        accept_language,
+                    )
+                    .to_owned();
+                if update_locale(&mut transaction, subscriber_id, &locale)
+                    .await
+                    .is_err()
+                {
+                    return HttpResponse::InternalServerError().finish();
+                }
+                (subscriber_id, locale)
+            }
+            // The conflicting row was deleted in the meantime.
+            Ok(None) | Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
     };
 
+    if add_subscriber_tags(&mut transaction, subscriber_id, &tags)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
     let subscription_token = generate_subscription_token();
 
     if store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
         .is_err()
     {
-        return HttpResponse::InternalServerError();
+        return HttpResponse::InternalServerError().finish();
     };
 
+    let preferences_token = PreferencesToken::generate(subscriber_id, &hmac_secret.0);
+    let preferences_link =
+        EmailClient::get_preferences_link(&base_url.0, preferences_token.as_ref());
     if email_client
-        .send_confirmation(&new_subscriber, &base_url.0, &subscription_token)
+        .send_confirmation(
+            &new_subscriber,
+            &base_url.0,
+            &subscription_token,
+            &preferences_link,
+            &confirmation_templates,
+            &locale,
+        )
         .await
         .is_err()
     {
-        return HttpResponse::InternalServerError();
+        return HttpResponse::InternalServerError().finish();
     };
 
     if transaction.commit().await.is_err() {
-        return HttpResponse::InternalServerError();
+        return HttpResponse::InternalServerError().finish();
     }
 
-    HttpResponse::Ok()
+    HttpResponse::Ok().finish()
 }
 
 #[tracing::instrument(
-    name = "Saving new subscriber details in the database",
+    name = "Looking up an existing subscriber",
     skip(subscriber, transaction)
 )]
-async fn insert_subscriber(
+async fn get_existing_subscriber(
     subscriber: &Subscriber,
+    list_id: Uuid,
     transaction: &mut Transaction<'_, Postgres>,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<(Uuid, Option<String>)>, sqlx::Error> {
     let subscriber_mail: &str = subscriber.email.as_ref();
-    let subscriber_id = uuid::Uuid::new_v4();
+    let result = sqlx::query!(
+        r#"SELECT id, locale FROM subscriptions WHERE list_id = $1 AND email = $2 FOR UPDATE"#,
+        list_id,
+        subscriber_mail,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| (r.id, r.locale)))
+}
+
+#[tracing::instrument(name = "Updating subscriber locale", skip(transaction))]
+async fn update_locale(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    locale: &str,
+) -> Result<(), sqlx::Error> {
     let query = sqlx::query!(
-        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status) 
-                    VALUES ($1, $2, $3, $4, $5)"#,
+        r#"UPDATE subscriptions SET locale = $2 WHERE id = $1"#,
         subscriber_id,
+        locale,
+    );
+    transaction.execute(query).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+pub enum StatusChange {
+    Changed,
+    Unchanged,
+    Rejected(SubscriptionStatus),
+    NotFound,
+}
+
+/// Moves a subscriber to `next` if `SubscriptionStatus::can_transition_to` allows it.
+/// Every status change after insertion goes through here or `rejoin_subscription`.
+#[tracing::instrument(name = "Changing subscription status", skip(transaction))]
+pub async fn change_subscription_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    next: SubscriptionStatus,
+) -> Result<StatusChange, sqlx::Error> {
+    set_status_if(transaction, subscriber_id, next, |current| {
+        current.can_transition_to(next)
+    })
+    .await
+}
+
+/// Confirms a subscriber rejoining from the preference center if
+/// `SubscriptionStatus::can_rejoin` allows it.
+#[tracing::instrument(name = "Rejoining a subscription", skip(transaction))]
+pub async fn rejoin_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<StatusChange, sqlx::Error> {
+    set_status_if(
+        transaction,
+        subscriber_id,
+        SubscriptionStatus::Confirmed,
+        SubscriptionStatus::can_rejoin,
+    )
+    .await
+}
+
+async fn set_status_if(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    next: SubscriptionStatus,
+    allowed: impl Fn(&SubscriptionStatus) -> bool,
+) -> Result<StatusChange, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let Some(row) = row else {
+        return Ok(StatusChange::NotFound);
+    };
+    let current = SubscriptionStatus::try_from(row.status).map_err(|e| {
+        tracing::error!("Failed to parse stored status: {}", e);
+        sqlx::Error::Decode(e.into())
+    })?;
+    if current == next {
+        return Ok(StatusChange::Unchanged);
+    }
+    if !allowed(&current) {
+        tracing::warn!(
+            "Refusing to move subscriber from {} to {}",
+            current.as_str(),
+            next.as_str()
+        );
+        return Ok(StatusChange::Rejected(current));
+    }
+    let query = sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE id = $1"#,
+        subscriber_id,
+        next.as_str(),
+    );
+    transaction.execute(query).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(StatusChange::Changed)
+}
+
+/// Returns `None` if the address is already on the list, including when a concurrent
+/// transaction inserted it first.
+#[tracing::instrument(
+    name = "Saving new subscriber details in the database",
+    skip(subscriber, transaction)
+)]
+pub async fn insert_subscriber(
+    subscriber: &Subscriber,
+    list_id: Uuid,
+    status: SubscriptionStatus,
+    consent_source: &str,
+    locale: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let subscriber_mail: &str = subscriber.email.as_ref();
+    let result = sqlx::query!(
+        r#"INSERT INTO subscriptions (id, list_id, email, name, subscribed_at, status, consent_source, locale) 
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                    ON CONFLICT (list_id, email) DO NOTHING
+                    RETURNING id"#,
+        uuid::Uuid::new_v4(),
+        list_id,
         subscriber_mail,
         subscriber.name.as_ref(),
         chrono::Utc::now(),
-        "confirmed",
-    );
-    transaction.execute(query).await.map_err(|e| {
+        status.as_str(),
+        consent_source,
+        locale,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(subscriber_id)
+    Ok(result.map(|r| r.id))
 }
 
 #[tracing::instrument(
     name = "Storing token into database",
     skip(transaction, subscription_token)
 )]
-async fn store_token(
+pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
     subscription_token: &str,
@@ -1,55 +1,67 @@
 use actix_web::{
     web::{self},
-    HttpResponse, Responder,
+    HttpResponse,
 };
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::domain::SubscriptionStatus;
+use crate::routes::{change_subscription_status, StatusChange};
+use crate::startup::SubscriptionTokenTtl;
+
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool, token_ttl))]
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
-) -> impl Responder {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError(),
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+) -> HttpResponse {
+    let token = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
     };
-    match id {
-        None => HttpResponse::Unauthorized(),
-        Some(id) => {
-            if confirm_subscriber(&pool, id).await.is_err() {
-                return HttpResponse::InternalServerError();
+    match token {
+        None => HttpResponse::Unauthorized().finish(),
+        Some((_, created_at)) if created_at + token_ttl.0 < Utc::now() => {
+            tracing::warn!("Subscription token expired");
+            HttpResponse::Gone().finish()
+        }
+        Some((id, _)) => {
+            let mut transaction = match pool.begin().await {
+                Ok(transaction) => transaction,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+            let response = match change_subscription_status(
+                &mut transaction,
+                id,
+                SubscriptionStatus::Confirmed,
+            )
+            .await
+            {
+                Ok(StatusChange::Changed | StatusChange::Unchanged) => HttpResponse::Ok().finish(),
+                Ok(StatusChange::Rejected(_)) => HttpResponse::Conflict().finish(),
+                Ok(StatusChange::NotFound) => HttpResponse::Unauthorized().finish(),
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+            if transaction.commit().await.is_err() {
+                return HttpResponse::InternalServerError().finish();
             }
-            HttpResponse::Ok()
+            response
         }
     }
 }
-#[tracing::instrument(name = "Mark subscriber as confirm", skip(pool, subscriber_id))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id,
-    )
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(())
-}
 #[tracing::instrument(name = "Get subscriber_id from token", skip(pool, token))]
 pub async fn get_subscriber_id_from_token(
     pool: &PgPool,
     token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+) -> Result<Option<(Uuid, DateTime<Utc>)>, sqlx::Error> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+        r#"SELECT subscriber_id, created_at FROM subscription_tokens WHERE subscription_token = $1"#,
         token,
     )
     .fetch_optional(pool)
@@ -58,5 +70,5 @@ pub async fn get_subscriber_id_from_token(
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.map(|r| (r.subscriber_id, r.created_at)))
 }
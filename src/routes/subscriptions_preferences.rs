@@ -0,0 +1,478 @@
+use std::fmt::Write;
+
+use actix_web::http::header::{ContentType, ACCEPT};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use minijinja::HtmlEscape;
+use secrecy::Secret;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{DigestFrequency, PreferencesToken, SubscriberName, SubscriptionStatus},
+    routes::{
+        change_subscription_status, rejoin_subscription, render_flash_messages, resolve_list,
+        see_other,
+    },
+    startup::HmacSecret,
+};
+
+/// Where the consent for lists joined from the preference center comes from.
+const PREFERENCE_CENTER_CONSENT: &str = "preference_center";
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesData {
+    name: Option<String>,
+    digest_frequency: Option<DigestFrequency>,
+    /// Slugs of every list to receive, the subscriber leaves any list missing from it.
+    lists: Option<Vec<String>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Preferences {
+    name: String,
+    email: String,
+    digest_frequency: DigestFrequency,
+    lists: Vec<ListPreference>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ListPreference {
+    slug: String,
+    name: String,
+    subscribed: bool,
+}
+
+/// The subscription a preferences token was issued for; its email ties together the
+/// subscriber's rows on every list.
+struct Owner {
+    email: String,
+    name: String,
+    digest_frequency: String,
+    locale: Option<String>,
+}
+
+/// The page behind the preferences link every email ends with. API clients asking for
+/// `application/json` get the preferences as JSON instead.
+#[tracing::instrument(
+    name = "Show subscriber preferences",
+    skip(parameters, pool, hmac_secret, flash_messages, request)
+)]
+pub async fn get_preferences(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    flash_messages: IncomingFlashMessages,
+    request: HttpRequest,
+) -> HttpResponse {
+    let subscriber_id = match parse_token(&parameters.token, &hmac_secret.0) {
+        Ok(subscriber_id) => subscriber_id,
+        Err(response) => return response,
+    };
+    let owner = match get_owner(pool.get_ref(), subscriber_id).await {
+        Ok(Some(owner)) => owner,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let lists = match get_list_preferences(pool.get_ref(), &owner.email).await {
+        Ok(lists) => lists,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let wants_json = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|header| header.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+    if wants_json {
+        return preferences_response(owner, lists);
+    }
+    let mut frequencies = String::new();
+    for (frequency, label) in [
+        (DigestFrequency::Immediate, "Every issue as it is published"),
+        (DigestFrequency::Daily, "A daily digest"),
+        (DigestFrequency::Weekly, "A weekly digest"),
+    ] {
+        writeln!(
+            frequencies,
+            r#"<option value="{}"{}>{}</option>"#,
+            frequency.as_str(),
+            if frequency.as_str() == owner.digest_frequency {
+                " selected"
+            } else {
+                ""
+            },
+            label
+        )
+        .unwrap();
+    }
+    let mut checkboxes = String::new();
+    for list in lists {
+        writeln!(
+            checkboxes,
+            r#"<label><input type="checkbox" name="lists" value="{}"{}> {}</label><br>"#,
+            HtmlEscape(&list.slug),
+            if list.subscribed { " checked" } else { "" },
+            HtmlEscape(&list.name)
+        )
+        .unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your preferences</title>
+</head>
+<body>
+    {}
+    <p>Preferences for {}</p>
+    <form action="/subscriptions/preferences?token={}" method="post">
+        <label>Name
+            <input type="text" name="name" value="{}">
+        </label>
+        <br>
+        <label>Emails
+            <select name="digest_frequency">
+                {}
+            </select>
+        </label>
+        <br>
+        <fieldset>
+            <legend>Lists</legend>
+            {}
+        </fieldset>
+        <button type="submit">Save preferences</button>
+        <button type="submit" name="unsubscribe_all" value="true">Unsubscribe from every list</button>
+    </form>
+</body>
+</html>"#,
+            render_flash_messages(&flash_messages),
+            HtmlEscape(&owner.email),
+            HtmlEscape(&parameters.token),
+            HtmlEscape(&owner.name),
+            frequencies,
+            checkboxes,
+        ))
+}
+
+#[tracing::instrument(
+    name = "Update subscriber preferences",
+    skip(parameters, body, pool, hmac_secret)
+)]
+pub async fn update_preferences(
+    parameters: web::Query<PreferencesParameters>,
+    body: web::Json<PreferencesData>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let subscriber_id = match parse_token(&parameters.token, &hmac_secret.0) {
+        Ok(subscriber_id) => subscriber_id,
+        Err(response) => return response,
+    };
+    match save_preferences(&pool, subscriber_id, body.into_inner()).await {
+        Ok((owner, lists)) => preferences_response(owner, lists),
+        Err(response) => response,
+    }
+}
+
+/// Submits the preferences page. The form is URL-encoded with one `lists` pair per
+/// ticked list, hence the pairs rather than a struct.
+#[tracing::instrument(
+    name = "Update subscriber preferences from the form",
+    skip(parameters, form, pool, hmac_secret)
+)]
+pub async fn submit_preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let subscriber_id = match parse_token(&parameters.token, &hmac_secret.0) {
+        Ok(subscriber_id) => subscriber_id,
+        Err(response) => return response,
+    };
+    let mut name = None;
+    let mut digest_frequency = None;
+    let mut lists = Vec::new();
+    let mut unsubscribe_all = false;
+    for (key, value) in form.into_inner() {
+        match key.as_str() {
+            "name" => name = Some(value),
+            "digest_frequency" => match DigestFrequency::try_from(value) {
+                Ok(frequency) => digest_frequency = Some(frequency),
+                Err(e) => return HttpResponse::BadRequest().body(e),
+            },
+            "lists" => lists.push(value),
+            "unsubscribe_all" => unsubscribe_all = true,
+            _ => {}
+        }
+    }
+    let data = if unsubscribe_all {
+        PreferencesData {
+            name: None,
+            digest_frequency: None,
+            lists: Some(Vec::new()),
+        }
+    } else {
+        PreferencesData {
+            name,
+            digest_frequency,
+            lists: Some(lists),
+        }
+    };
+    match save_preferences(&pool, subscriber_id, data).await {
+        Ok(_) if unsubscribe_all => {
+            FlashMessage::info("You have been unsubscribed from every list.").send()
+        }
+        Ok(_) => FlashMessage::info("Your preferences have been saved.").send(),
+        Err(response) if response.status().is_client_error() => {
+            FlashMessage::error("Your preferences could not be saved, check your name.").send()
+        }
+        Err(response) => return response,
+    }
+    see_other(&format!("/subscriptions/preferences?token={}", parameters.token))
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber from every list",
+    skip(parameters, pool, hmac_secret)
+)]
+pub async fn unsubscribe_from_all(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let subscriber_id = match parse_token(&parameters.token, &hmac_secret.0) {
+        Ok(subscriber_id) => subscriber_id,
+        Err(response) => return response,
+    };
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let owner = match get_owner(&mut *transaction, subscriber_id).await {
+        Ok(Some(owner)) => owner,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if set_lists(&mut transaction, &owner, &[]).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().finish()
+}
+
+fn parse_token(token: &str, hmac_secret: &Secret<String>) -> Result<Uuid, HttpResponse> {
+    PreferencesToken::parse(token, hmac_secret).map_err(|e| {
+        tracing::error!("Invalid preferences token: {}", e);
+        HttpResponse::Unauthorized().finish()
+    })
+}
+
+/// Applies whatever `data` sets, in one transaction, and returns the resulting preferences.
+async fn save_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    data: PreferencesData,
+) -> Result<(Owner, Vec<ListPreference>), HttpResponse> {
+    let name = data
+        .name
+        .map(SubscriberName::parse)
+        .transpose()
+        .map_err(|e| HttpResponse::BadRequest().body(e))?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    let mut owner = match get_owner(&mut *transaction, subscriber_id).await {
+        Ok(Some(owner)) => owner,
+        Ok(None) => return Err(HttpResponse::NotFound().finish()),
+        Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+    };
+    if let Some(name) = name {
+        owner.name = name.as_ref().to_owned();
+    }
+    if let Some(digest_frequency) = data.digest_frequency {
+        owner.digest_frequency = digest_frequency.as_str().to_owned();
+    }
+    update_details(&mut transaction, &owner)
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    if let Some(slugs) = data.lists {
+        let mut list_ids = Vec::with_capacity(slugs.len());
+        for slug in &slugs {
+            list_ids.push(resolve_list(&mut *transaction, Some(slug)).await?);
+        }
+        // A list named twice must not be joined twice.
+        list_ids.sort();
+        list_ids.dedup();
+        set_lists(&mut transaction, &owner, &list_ids)
+            .await
+            .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    }
+    let lists = get_list_preferences(&mut *transaction, &owner.email)
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    Ok((owner, lists))
+}
+
+fn preferences_response(owner: Owner, lists: Vec<ListPreference>) -> HttpResponse {
+    let digest_frequency = match DigestFrequency::try_from(owner.digest_frequency) {
+        Ok(digest_frequency) => digest_frequency,
+        Err(e) => {
+            tracing::error!("Failed to parse stored digest frequency: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    HttpResponse::Ok().json(Preferences {
+        name: owner.name,
+        email: owner.email,
+        digest_frequency,
+        lists,
+    })
+}
+
+#[tracing::instrument(name = "Get preferences owner", skip(executor))]
+async fn get_owner(
+    executor: impl Executor<'_, Database = Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<Owner>, sqlx::Error> {
+    sqlx::query_as!(
+        Owner,
+        r#"SELECT email, name, digest_frequency, locale FROM subscriptions WHERE id = $1"#,
+        subscriber_id,
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get list preferences", skip(executor))]
+async fn get_list_preferences(
+    executor: impl Executor<'_, Database = Postgres>,
+    email: &str,
+) -> Result<Vec<ListPreference>, sqlx::Error> {
+    sqlx::query_as!(
+        ListPreference,
+        r#"
+        SELECT l.slug, l.name, COALESCE(s.status = 'confirmed', false) AS "subscribed!"
+        FROM lists l
+        LEFT JOIN subscriptions s ON s.list_id = l.list_id AND s.email = $1
+        ORDER BY l.created_at
+        "#,
+        email,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Update subscriber details", skip(transaction, owner))]
+async fn update_details(
+    transaction: &mut Transaction<'_, Postgres>,
+    owner: &Owner,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2, digest_frequency = $3 WHERE email = $1"#,
+        owner.email,
+        owner.name,
+        owner.digest_frequency,
+    );
+    transaction.execute(query).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// Leaves every list not in `list_ids` and joins the rest. Holding a token mailed to the
+/// address proves the subscriber owns it, so joined lists need no further confirmation.
+#[tracing::instrument(name = "Set subscribed lists", skip(transaction, owner))]
+async fn set_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    owner: &Owner,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    let subscriptions = sqlx::query!(
+        r#"SELECT id, list_id FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        owner.email,
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    for subscription in &subscriptions {
+        if !list_ids.contains(&subscription.list_id) {
+            change_subscription_status(
+                transaction,
+                subscription.id,
+                SubscriptionStatus::Unsubscribed,
+            )
+            .await?;
+        }
+    }
+    for &list_id in list_ids {
+        match subscriptions.iter().find(|s| s.list_id == list_id) {
+            Some(subscription) => rejoin_list(transaction, subscription.id).await?,
+            None => insert_confirmed_subscription(transaction, owner, list_id).await?,
+        }
+    }
+    Ok(())
+}
+
+/// Subscriptions that were left go straight back to confirmed, see
+/// `SubscriptionStatus::can_rejoin`; bounces and complaints stay put.
+async fn rejoin_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    rejoin_subscription(transaction, subscriber_id).await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Join a list from preferences", skip(transaction, owner))]
+async fn insert_confirmed_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    owner: &Owner,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, list_id, email, name, subscribed_at, status, digest_frequency, locale, consent_source)
+        VALUES ($1, $2, $3, $4, now(), 'confirmed', $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        list_id,
+        owner.email,
+        owner.name,
+        owner.digest_frequency,
+        owner.locale,
+        PREFERENCE_CENTER_CONSENT,
+    );
+    transaction.execute(query).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use actix_web::{
+    http::header::ContentType,
+    web::{self},
+    HttpResponse,
+};
+use minijinja::HtmlEscape;
+use sqlx::PgPool;
+
+use crate::{
+    domain::{SubscriptionStatus, UnsubscribeToken},
+    routes::change_subscription_status,
+    startup::HmacSecret,
+};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+/// The page the footer link opens. Link scanners and prefetchers follow links with GET,
+/// so it only asks for confirmation; the form posts back to `unsubscribe`.
+#[tracing::instrument(name = "Show the unsubscribe page", skip(parameters, hmac_secret))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if let Err(e) = UnsubscribeToken::parse(&parameters.token, &hmac_secret.0) {
+        tracing::error!("Invalid unsubscribe token: {}", e);
+        return HttpResponse::Unauthorized().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving this newsletter?</p>
+    <form action="/subscriptions/unsubscribe?token={}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            HtmlEscape(&parameters.token)
+        ))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool, hmac_secret))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let subscriber_id = match UnsubscribeToken::parse(&parameters.token, &hmac_secret.0) {
+        Ok(subscriber_id) => subscriber_id,
+        Err(e) => {
+            tracing::error!("Invalid unsubscribe token: {}", e);
+            return HttpResponse::Unauthorized().finish();
+        }
+    };
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // Whatever state the subscriber was in, they won't be mailed again; report success.
+    if change_subscription_status(
+        &mut transaction,
+        subscriber_id,
+        SubscriptionStatus::Unsubscribed,
+    )
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed.</p>
+</body>
+</html>"#,
+        )
+}
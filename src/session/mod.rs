@@ -0,0 +1,5 @@
+mod store;
+mod typed_session;
+
+pub use store::*;
+pub use typed_session::*;
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use anyhow::Context;
+use chrono::Utc;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sqlx::types::Json;
+use sqlx::PgPool;
+
+type SessionState = HashMap<String, String>;
+
+/// Keeps session state server-side in the `sessions` table, the cookie only carries the key.
+#[derive(Clone)]
+pub struct PostgresSessionStore {
+    pool: PgPool,
+}
+
+impl PostgresSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn generate_session_key() -> SessionKey {
+    let mut rng = thread_rng();
+    let key: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(64)
+        .collect();
+    key.try_into()
+        .expect("A 64 character key is a valid session key")
+}
+
+fn expires_at(ttl: &Duration) -> chrono::DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(ttl.whole_seconds())
+}
+
+#[async_trait::async_trait(?Send)]
+impl SessionStore for PostgresSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let row = sqlx::query!(
+            r#"
+                SELECT state AS "state: Json<SessionState>"
+                FROM sessions
+                WHERE session_key = $1 AND expires_at > now()
+            "#,
+            session_key.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to load a session")
+        .map_err(LoadError::Other)?;
+        Ok(row.map(|row| row.state.0))
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let session_key = generate_session_key();
+        // Expired sessions are never loaded again, this is as good a time as any to drop them.
+        sqlx::query!(r#"DELETE FROM sessions WHERE expires_at <= now()"#)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete expired sessions")
+            .map_err(SaveError::Other)?;
+        sqlx::query!(
+            r#"INSERT INTO sessions (session_key, state, expires_at) VALUES ($1, $2, $3)"#,
+            session_key.as_ref(),
+            Json(&session_state) as _,
+            expires_at(ttl),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to save a session")
+        .map_err(SaveError::Other)?;
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE sessions
+                SET state = $2, expires_at = $3
+                WHERE session_key = $1 AND expires_at > now()
+            "#,
+            session_key.as_ref(),
+            Json(&session_state) as _,
+            expires_at(ttl),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to update a session")
+        .map_err(UpdateError::Other)?;
+        if result.rows_affected() == 1 {
+            return Ok(session_key);
+        }
+        // The session expired in the meantime, so it starts over under a new key.
+        self.save(session_state, ttl).await.map_err(|e| match e {
+            SaveError::Serialization(e) => UpdateError::Serialization(e),
+            SaveError::Other(e) => UpdateError::Other(e),
+        })
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &Duration,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"UPDATE sessions SET expires_at = $2 WHERE session_key = $1"#,
+            session_key.as_ref(),
+            expires_at(ttl),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to extend a session")?;
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE session_key = $1"#,
+            session_key.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to delete a session")?;
+        Ok(())
+    }
+}
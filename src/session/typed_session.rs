@@ -0,0 +1,54 @@
+use std::future::{ready, Ready};
+
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The session of an admin logged in through `/login`, with typed accessors for what we keep in it.
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+
+    /// Issues a new session key, so a key planted before login is useless afterwards.
+    pub fn renew(&self) {
+        self.0.renew();
+    }
+    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)
+    }
+    pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::USER_ID_KEY)
+    }
+    pub fn log_out(self) {
+        self.0.purge()
+    }
+    /// Ends every session `user_id` is logged in with, e.g. once the account is disabled.
+    #[tracing::instrument(name = "Log a user out everywhere", skip(pool))]
+    pub async fn log_out_everywhere(user_id: Uuid, pool: &PgPool) -> Result<(), sqlx::Error> {
+        // Session values are stored JSON-encoded, the same way `insert_user_id` wrote it.
+        let user_id = serde_json::to_string(&user_id).expect("A uuid serializes to JSON");
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE state ->> 'user_id' = $1"#,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+}
+
+impl FromRequest for TypedSession {
+    type Error = <Session as FromRequest>::Error;
+    type Future = Ready<Result<TypedSession, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(TypedSession(req.get_session())))
+    }
+}
@@ -1,81 +1,340 @@
-use crate::configuration::Settings;
-use crate::email_client::EmailClient;
+use crate::configuration::{EmailTransportSettings, Settings};
+use crate::email_client::{
+    ConfirmationTemplates, EmailClient, EmailTransport, HttpApiTransport, InMemoryTransport,
+    MaildirTransport, SmtpTransport,
+};
+use crate::confirmation_worker::run_confirmation_worker_until_stopped;
+use crate::digest_worker::run_digest_worker_until_stopped;
+use crate::issue_delivery_worker::run_worker_until_stopped;
+use crate::issue_scheduler::run_scheduler_until_stopped;
+use crate::routes::admin_dashboard;
+use crate::routes::cancel_scheduled_issue;
+use crate::routes::change_password;
+use crate::routes::change_password_form;
 use crate::routes::confirm;
+use crate::routes::create_api_token;
+use crate::routes::create_draft;
+use crate::routes::create_list;
+use crate::routes::create_user;
+use crate::routes::delete_draft;
+use crate::routes::delete_subscriber;
+use crate::routes::delete_subscriber_tag;
+use crate::routes::delete_user;
+use crate::routes::edit_subscriber;
+use crate::routes::edit_user;
+use crate::routes::export_subscribers;
+use crate::routes::get_archived_issue;
+use crate::routes::get_draft;
+use crate::routes::get_preferences;
+use crate::routes::get_subscriber;
+use crate::routes::get_subscriber_tags;
 use crate::routes::health_check;
+use crate::routes::import_subscribers;
+use crate::routes::list_api_tokens;
+use crate::routes::list_dead_letters;
+use crate::routes::list_drafts;
+use crate::routes::list_lists;
+use crate::routes::list_scheduled_issues;
+use crate::routes::list_subscribers;
+use crate::routes::list_users;
+use crate::routes::log_out;
+use crate::routes::login;
+use crate::routes::login_form;
+use crate::routes::newsletter_form;
+use crate::routes::preview_draft;
+use crate::routes::publish_draft;
 use crate::routes::publish_newsletter;
+use crate::routes::replace_subscriber_tags;
+use crate::routes::reschedule_issue;
+use crate::routes::revoke_api_token;
+use crate::routes::submit_newsletter_form;
+use crate::routes::submit_preferences_form;
 use crate::routes::subscribe;
+use crate::routes::test_send_draft;
+use crate::routes::unsubscribe;
+use crate::routes::unsubscribe_form;
+use crate::routes::unsubscribe_from_all;
+use crate::routes::update_draft;
+use crate::routes::update_preferences;
+use crate::session::PostgresSessionStore;
+use actix_session::SessionMiddleware;
+use actix_web::cookie::Key;
 use actix_web::dev::Server;
 use actix_web::{web, App, HttpServer};
+use actix_web_flash_messages::storage::CookieMessageStore;
+use actix_web_flash_messages::FlashMessagesFramework;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
 pub struct Application {
     port: u16,
     server: Server,
+    db_pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    confirmation_templates: ConfirmationTemplates,
 }
 impl Application {
     pub async fn build(configuration: &Settings) -> Result<Self, std::io::Error> {
+        let email_client = get_email_client(configuration)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        Self::build_with_email_client(configuration, email_client).await
+    }
+    /// Like `build`, but delivers through `email_client` instead of the configured transport.
+    pub async fn build_with_email_client(
+        configuration: &Settings,
+        email_client: EmailClient,
+    ) -> Result<Self, std::io::Error> {
         let db_pool = PgPool::connect_lazy_with(configuration.database.with_db());
 
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
         );
-        let mailer = match configuration.email_client.test_sever {
-            false => EmailClient::get_gmail_mailer(
-                &configuration.email_client.user_name,
-                &configuration.email_client.password,
-            ),
-            true => EmailClient::get_test_mailer(
-                &configuration.smtp_sever.smtp_host,
-                &configuration.smtp_sever.smtp_port,
-            ),
-        };
-        let email_client = EmailClient::new(
-            &configuration.email_client.user_name,
-            &configuration.email_client.user_mail,
-            mailer,
-        );
+        let confirmation_templates = ConfirmationTemplates::load(
+            &configuration.email_client.templates_directory,
+            &configuration.email_client.default_locale,
+        )
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
         let server = run(
             listener,
+            db_pool.clone(),
+            email_client.clone(),
+            &configuration.application.base_url,
+            configuration.application.hmac_secret.clone(),
+            configuration.application.session_key.clone(),
+            chrono::Duration::hours(configuration.application.subscription_token_ttl_hours),
+            confirmation_templates.clone(),
+        )?;
+        Ok(Self {
+            port,
+            server,
             db_pool,
             email_client,
-            &*configuration.application.base_url,
-        )?;
-        Ok(Self { port, server })
+            base_url: configuration.application.base_url.clone(),
+            hmac_secret: configuration.application.hmac_secret.clone(),
+            confirmation_templates,
+        })
     }
     pub fn port(&self) -> u16 {
         self.port
     }
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        tokio::select! {
+            outcome = self.server => outcome,
+            outcome = run_scheduler_until_stopped(self.db_pool.clone()) => outcome,
+            outcome = run_confirmation_worker_until_stopped(
+                self.db_pool.clone(),
+                self.email_client.clone(),
+                self.base_url.clone(),
+                self.hmac_secret.clone(),
+                self.confirmation_templates,
+            ) => outcome,
+            outcome = run_digest_worker_until_stopped(
+                self.db_pool.clone(),
+                self.email_client.clone(),
+                self.base_url.clone(),
+                self.hmac_secret.clone(),
+            ) => outcome,
+            outcome = run_worker_until_stopped(
+                self.db_pool,
+                self.email_client,
+                self.base_url,
+                self.hmac_secret,
+            ) => outcome,
+        }
+    }
+}
+
+pub fn get_email_client(configuration: &Settings) -> Result<EmailClient, anyhow::Error> {
+    let transport: Arc<dyn EmailTransport> = match &configuration.email_client.transport {
+        EmailTransportSettings::Smtp(smtp) => Arc::new(SmtpTransport::from_settings(smtp)?),
+        EmailTransportSettings::HttpApi(http_api) => {
+            Arc::new(HttpApiTransport::from_settings(http_api)?)
+        }
+        EmailTransportSettings::InMemory => Arc::new(InMemoryTransport::default()),
+        EmailTransportSettings::Maildir { directory } => Arc::new(MaildirTransport::new(directory)),
+    };
+    let email_client = EmailClient::new(
+        &configuration.email_client.user_name,
+        &configuration.email_client.user_mail,
+        transport,
+    );
+    match &configuration.email_client.dkim {
+        Some(dkim) => Ok(email_client.with_dkim(dkim.signing_config()?)),
+        None => Ok(email_client),
     }
 }
 
 pub struct ApplicationBaseUrl(pub String);
+pub struct HmacSecret(pub Secret<String>);
+pub struct SubscriptionTokenTtl(pub chrono::Duration);
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     lisener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: &str,
+    hmac_secret: Secret<String>,
+    session_key: Secret<String>,
+    subscription_token_ttl: chrono::Duration,
+    confirmation_templates: ConfirmationTemplates,
 ) -> Result<Server, std::io::Error> {
+    // Signs the session and flash message cookies.
+    let secret_key = Key::try_from(session_key.expose_secret().as_bytes()).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "The session_key must be at least 64 bytes long",
+        )
+    })?;
+    let message_store = CookieMessageStore::builder(secret_key.clone()).build();
+    let message_framework = FlashMessagesFramework::builder(message_store).build();
+    let session_store = PostgresSessionStore::new(db_pool.clone());
     let base_url = web::Data::new(ApplicationBaseUrl(base_url.to_owned()));
+    let hmac_secret = web::Data::new(HmacSecret(hmac_secret));
+    let subscription_token_ttl = web::Data::new(SubscriptionTokenTtl(subscription_token_ttl));
+    let confirmation_templates = web::Data::new(confirmation_templates);
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let sever = HttpServer::new(move || {
         App::new()
+            .wrap(message_framework.clone())
+            .wrap(SessionMiddleware::new(
+                session_store.clone(),
+                secret_key.clone(),
+            ))
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
+            .route("/login", web::get().to(login_form))
+            .route("/login", web::post().to(login))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe_form))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/subscriptions/preferences", web::get().to(get_preferences))
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(submit_preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::put().to(update_preferences),
+            )
+            .route(
+                "/subscriptions/preferences/unsubscribe",
+                web::post().to(unsubscribe_from_all),
+            )
             .route("/newsletter", web::post().to(publish_newsletter))
+            .route(
+                "/newsletter/archive/{newsletter_issue_id}",
+                web::get().to(get_archived_issue),
+            )
+            .route("/admin/dashboard", web::get().to(admin_dashboard))
+            .route("/admin/newsletters", web::get().to(newsletter_form))
+            .route("/admin/newsletters", web::post().to(submit_newsletter_form))
+            .route("/admin/password", web::get().to(change_password_form))
+            .route("/admin/password", web::post().to(change_password))
+            .route("/admin/logout", web::post().to(log_out))
+            .route(
+                "/admin/deliveries/dead_letters",
+                web::get().to(list_dead_letters),
+            )
+            .route(
+                "/admin/newsletters/scheduled",
+                web::get().to(list_scheduled_issues),
+            )
+            .route(
+                "/admin/newsletters/scheduled/{newsletter_issue_id}",
+                web::patch().to(reschedule_issue),
+            )
+            .route(
+                "/admin/newsletters/scheduled/{newsletter_issue_id}",
+                web::delete().to(cancel_scheduled_issue),
+            )
+            .route("/admin/subscribers", web::get().to(list_subscribers))
+            .route(
+                "/admin/subscribers/import",
+                web::post().to(import_subscribers),
+            )
+            .route(
+                "/admin/subscribers/export",
+                web::get().to(export_subscribers),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}",
+                web::get().to(get_subscriber),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}",
+                web::patch().to(edit_subscriber),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}",
+                web::delete().to(delete_subscriber),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}/tags",
+                web::get().to(get_subscriber_tags),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}/tags",
+                web::put().to(replace_subscriber_tags),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}/tags/{tag}",
+                web::delete().to(delete_subscriber_tag),
+            )
+            .route("/admin/users", web::get().to(list_users))
+            .route("/admin/users", web::post().to(create_user))
+            .route("/admin/users/{user_id}", web::patch().to(edit_user))
+            .route("/admin/users/{user_id}", web::delete().to(delete_user))
+            .route("/admin/tokens", web::get().to(list_api_tokens))
+            .route("/admin/tokens", web::post().to(create_api_token))
+            .route(
+                "/admin/tokens/{token_id}",
+                web::delete().to(revoke_api_token),
+            )
+            .route("/admin/lists", web::get().to(list_lists))
+            .route("/admin/lists", web::post().to(create_list))
+            .route("/admin/newsletters/drafts", web::get().to(list_drafts))
+            .route("/admin/newsletters/drafts", web::post().to(create_draft))
+            .route(
+                "/admin/newsletters/drafts/{newsletter_issue_id}",
+                web::get().to(get_draft),
+            )
+            .route(
+                "/admin/newsletters/drafts/{newsletter_issue_id}",
+                web::put().to(update_draft),
+            )
+            .route(
+                "/admin/newsletters/drafts/{newsletter_issue_id}",
+                web::delete().to(delete_draft),
+            )
+            .route(
+                "/admin/newsletters/drafts/{newsletter_issue_id}/preview",
+                web::get().to(preview_draft),
+            )
+            .route(
+                "/admin/newsletters/drafts/{newsletter_issue_id}/test_send",
+                web::post().to(test_send_draft),
+            )
+            .route(
+                "/admin/newsletters/drafts/{newsletter_issue_id}/publish",
+                web::post().to(publish_draft),
+            )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(hmac_secret.clone())
+            .app_data(subscription_token_ttl.clone())
+            .app_data(confirmation_templates.clone())
     })
     .listen(lisener)?
     .run();
@@ -0,0 +1,36 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
+    let app = spawn_app().await;
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_dashboard_shows_subscriber_counts() {
+    let app = spawn_app().await;
+    app.post_subscriptions("name=testName&email=testEmail%40gmail.com")
+        .await
+        .error_for_status()
+        .expect("Failed to create subscriber");
+    app.login().await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("0 confirmed subscribers, 1 waiting for confirmation"));
+}
+
+#[tokio::test]
+async fn logout_clears_session_state() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(r#"<p class="info"><i>You have successfully logged out.</i></p>"#));
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
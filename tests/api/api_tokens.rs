@@ -0,0 +1,233 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+
+struct CreatedToken {
+    token_id: String,
+    token: String,
+}
+
+async fn create_token(app: &TestApp, scopes: &[&str]) -> CreatedToken {
+    let response = app
+        .post_api_tokens(serde_json::json!({
+            "name": "CI",
+            "scopes": scopes,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    CreatedToken {
+        token_id: body["token_id"].as_str().unwrap().to_owned(),
+        token: body["token"].as_str().unwrap().to_owned(),
+    }
+}
+
+async fn publish_with_token(app: &TestApp, token: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/newsletter", &app.address))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "subject": "Release notes",
+            "content": "<p>What's new</p>",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_subscribers_with_token(app: &TestApp, token: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/admin/subscribers", &app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn a_token_is_only_returned_once_and_stored_hashed() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &["newsletter:publish"]).await;
+    assert!(created.token.starts_with("nlt_"));
+
+    let saved = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved token");
+    assert!(!saved.token_hash.contains(&created.token[4..]));
+
+    let tokens: serde_json::Value = app.get_api_tokens().await.json().await.unwrap();
+    let tokens = tokens.as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0]["token_id"], created.token_id.as_str());
+    assert_eq!(
+        tokens[0]["scopes"],
+        serde_json::json!(["newsletter:publish"])
+    );
+    assert!(tokens[0].get("token").is_none());
+    assert!(tokens[0]["last_used_at"].is_null());
+}
+
+#[tokio::test]
+async fn a_token_with_the_publish_scope_can_publish() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &["newsletter:publish"]).await;
+
+    let response = publish_with_token(&app, &created.token).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let tokens: serde_json::Value = app.get_api_tokens().await.json().await.unwrap();
+    assert!(!tokens[0]["last_used_at"].is_null());
+}
+
+#[tokio::test]
+async fn a_token_without_the_scope_is_forbidden() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &["subscribers:read"]).await;
+
+    let response = publish_with_token(&app, &created.token).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let tokens: serde_json::Value = app.get_api_tokens().await.json().await.unwrap();
+    assert!(tokens[0]["last_used_at"].is_null());
+    let response = get_subscribers_with_token(&app, &created.token).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_bearer_scheme_is_case_insensitive() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &["subscribers:read"]).await;
+
+    for scheme in ["bearer", "BEARER"] {
+        let response = reqwest::Client::new()
+            .get(format!("{}/admin/subscribers", &app.address))
+            .header("Authorization", format!("{} {}", scheme, created.token))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn tokens_are_not_accepted_by_endpoints_without_a_scope() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &["newsletter:publish", "subscribers:read"]).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/lists", &app.address))
+        .bearer_auth(&created.token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+    // Nor can a token mint another one.
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/tokens", &app.address))
+        .bearer_auth(&created.token)
+        .json(&serde_json::json!({"name": "Copy", "scopes": ["newsletter:publish"]}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unknown_and_malformed_tokens_are_rejected() {
+    let app = spawn_app().await;
+    for token in [format!("nlt_{}", "a".repeat(40)), "garbage".to_string()] {
+        let response = publish_with_token(&app, &token).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn expired_tokens_are_rejected() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &["newsletter:publish"]).await;
+    sqlx::query!("UPDATE api_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to expire token");
+
+    let response = publish_with_token(&app, &created.token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_rejected() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &["newsletter:publish"]).await;
+
+    let response = app.delete_api_token(&created.token_id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = publish_with_token(&app, &created.token).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.delete_api_token(&created.token_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn tokens_of_a_disabled_admin_are_rejected() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &["newsletter:publish"]).await;
+    sqlx::query!(
+        "UPDATE users SET disabled_at = now() WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to disable user");
+
+    let response = publish_with_token(&app, &created.token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn create_token_returns_a_400_for_invalid_data() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": " ", "scopes": ["newsletter:publish"]}),
+            "blank name",
+        ),
+        (
+            serde_json::json!({"name": "CI", "scopes": []}),
+            "missing scopes",
+        ),
+        (
+            serde_json::json!({"name": "CI", "scopes": ["lists:write"]}),
+            "unknown scope",
+        ),
+        (
+            serde_json::json!({
+                "name": "CI",
+                "scopes": ["newsletter:publish"],
+                "expires_at": "2020-01-01T00:00:00Z",
+            }),
+            "past expiry",
+        ),
+    ];
+    for (body, description) in test_cases {
+        let response = app.post_api_tokens(body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request for a {}",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    let app = spawn_app().await;
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/tokens", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.delete_api_token(&Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
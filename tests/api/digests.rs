@@ -0,0 +1,171 @@
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn insert_confirmed_subscriber(app: &TestApp, digest_frequency: &str) -> (Uuid, String) {
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET digest_frequency = $2 WHERE id = $1",
+        subscriber_id,
+        digest_frequency,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to set digest frequency");
+    (subscriber_id, email)
+}
+
+async fn publish_issue(app: &TestApp) -> String {
+    let subject = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "html_content": format!("<p>Body of {}</p>", subject),
+            "text_content": format!("Body of {}", subject),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    subject
+}
+
+#[tokio::test]
+async fn digest_subscribers_are_held_back_until_their_next_digest() {
+    let app = spawn_app().await;
+    let (_, immediate_email) = insert_confirmed_subscriber(&app, "immediate").await;
+    let (daily_id, daily_email) = insert_confirmed_subscriber(&app, "daily").await;
+    let (weekly_id, weekly_email) = insert_confirmed_subscriber(&app, "weekly").await;
+
+    let subject = publish_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert!(app.check_newsletter_delivered(&subject, &immediate_email));
+    assert!(!app.check_newsletter_delivered(&subject, &daily_email));
+    assert!(!app.check_newsletter_delivered(&subject, &weekly_email));
+    for (subscriber_id, next_digest) in [
+        (
+            daily_id,
+            "date_trunc('day', now(), 'UTC') + interval '1 day'",
+        ),
+        (
+            weekly_id,
+            "date_trunc('week', now(), 'UTC') + interval '1 week'",
+        ),
+    ] {
+        let held_back: bool = sqlx::query_scalar(&format!(
+            "SELECT status = 'digest' AND execute_after = {} FROM issue_delivery_queue WHERE subscriber_id = $1",
+            next_digest
+        ))
+        .bind(subscriber_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch digest task");
+        assert!(held_back);
+    }
+}
+
+#[tokio::test]
+async fn due_issues_are_batched_into_a_single_digest() {
+    let app = spawn_app().await;
+    let (subscriber_id, email) = insert_confirmed_subscriber(&app, "daily").await;
+    let first_subject = publish_issue(&app).await;
+    let second_subject = publish_issue(&app).await;
+
+    app.send_all_digests_now().await;
+
+    assert!(!app.check_newsletter_delivered(&first_subject, &email));
+    assert!(!app.check_newsletter_delivered(&second_subject, &email));
+    let digest = app
+        .get_delivered_newsletter("Your newsletter digest: 2 new issues", &email)
+        .expect("No digest was delivered");
+    for subject in [&first_subject, &second_subject] {
+        assert!(digest.html.contains(&format!("Body of {}", subject)));
+        assert!(digest.text.contains(&format!("Body of {}", subject)));
+    }
+    assert!(digest.html.find(&first_subject) < digest.html.find(&second_subject));
+    let statuses = sqlx::query_scalar!(
+        "SELECT status FROM issue_delivery_queue WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch digest tasks");
+    assert_eq!(statuses, vec!["delivered", "delivered"]);
+}
+
+#[tokio::test]
+async fn an_address_on_several_lists_gets_a_single_digest() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let (_, email) = insert_confirmed_subscriber(&app, "daily").await;
+    app.insert_confirmed_subscriber("rust-weekly", &email).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET digest_frequency = 'daily' WHERE email = $1",
+        email,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to set digest frequency");
+    let default_subject = publish_issue(&app).await;
+    let rust_weekly_subject = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": rust_weekly_subject,
+            "html_content": format!("<p>Body of {}</p>", rust_weekly_subject),
+            "text_content": format!("Body of {}", rust_weekly_subject),
+            "list": "rust-weekly",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.send_all_digests_now().await;
+
+    let digest = app
+        .get_delivered_newsletter("Your newsletter digest: 2 new issues", &email)
+        .expect("No digest was delivered");
+    for subject in [&default_subject, &rust_weekly_subject] {
+        assert!(digest.text.contains(&format!("Body of {}", subject)));
+    }
+    assert!(!app.check_newsletter_delivered(&default_subject, &email));
+    assert!(!app.check_newsletter_delivered(&rust_weekly_subject, &email));
+}
+
+#[tokio::test]
+async fn a_digest_with_a_single_issue_goes_out_as_that_issue() {
+    let app = spawn_app().await;
+    let (_, email) = insert_confirmed_subscriber(&app, "weekly").await;
+    let subject = publish_issue(&app).await;
+
+    app.send_all_digests_now().await;
+
+    let issue = app
+        .get_delivered_newsletter(&subject, &email)
+        .expect("The issue was not delivered");
+    assert!(issue.text.contains(&format!("Body of {}", subject)));
+}
+
+#[tokio::test]
+async fn digests_are_skipped_for_subscribers_who_left_in_the_meantime() {
+    let app = spawn_app().await;
+    let (subscriber_id, email) = insert_confirmed_subscriber(&app, "daily").await;
+    let subject = publish_issue(&app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to unsubscribe");
+
+    app.send_all_digests_now().await;
+
+    assert!(!app.check_newsletter_delivered(&subject, &email));
+    let status = sqlx::query_scalar!(
+        "SELECT status FROM issue_delivery_queue WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch digest task");
+    assert_eq!(status, "skipped");
+}
@@ -0,0 +1,92 @@
+use crate::helpers::{spawn_app_with_email_client, TestApp};
+use base64::engine::general_purpose;
+use base64::Engine;
+use mail_auth::common::parse::TxtRecordParser;
+use mail_auth::common::verify::DomainKey;
+use mail_auth::{AuthenticatedMessage, DkimResult, Resolver};
+use rust_email_newsletter::configuration::{DkimAlgorithm, DkimSettings};
+use rust_email_newsletter::startup::get_email_client;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const SELECTOR: &str = "newsletter";
+const DOMAIN: &str = "example.com";
+/// Public half of tests/fixtures/dkim_ed25519.key, as it would be published in DNS.
+const PUBLIC_KEY: &str = "e0pfuKYE4/eOk7RppK2r2ge8RVRkVH8II+tOgoikXjU=";
+
+async fn spawn_signing_app() -> TestApp {
+    spawn_app_with_email_client(|configuration| {
+        let dkim = DkimSettings {
+            selector: SELECTOR.to_owned(),
+            domain: DOMAIN.to_owned(),
+            algorithm: DkimAlgorithm::Ed25519,
+            private_key_file: "tests/fixtures/dkim_ed25519.key".to_owned(),
+        };
+        get_email_client(configuration)
+            .expect("Failed to build email client")
+            .with_dkim(dkim.signing_config().expect("Failed to load DKIM key"))
+    })
+    .await
+}
+
+async fn verify_dkim(raw_message: &str) -> Vec<DkimResult> {
+    let raw_message = general_purpose::STANDARD
+        .decode(raw_message)
+        .expect("Captured message is not base64");
+    let message = AuthenticatedMessage::parse(&raw_message).expect("Failed to parse message");
+    let resolver = Resolver::new_cloudflare().expect("Failed to create resolver");
+    resolver.txt_add(
+        format!("{}._domainkey.{}.", SELECTOR, DOMAIN),
+        DomainKey::parse(format!("v=DKIM1; k=ed25519; p={}", PUBLIC_KEY).as_bytes()).unwrap(),
+        Instant::now() + Duration::from_secs(3600),
+    );
+    resolver
+        .verify_dkim(&message)
+        .await
+        .into_iter()
+        .map(|output| output.result().clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn confirmation_emails_carry_a_valid_dkim_signature() {
+    let app = spawn_signing_app().await;
+    let email = format!("{}@gmail.com", Uuid::new_v4());
+    app.post_subscriptions(format!("name=testName&email={}", urlencoding(&email)))
+        .await
+        .error_for_status()
+        .expect("Failed to create subscriber");
+
+    let message = app
+        .get_delivered_newsletter("Kither's newsletter email confirmation", &email)
+        .expect("Confirmation email was not captured");
+    assert_eq!(verify_dkim(&message.raw).await, vec![DkimResult::Pass]);
+}
+
+#[tokio::test]
+async fn newsletters_carry_a_valid_dkim_signature_over_the_unsubscribe_headers() {
+    let app = spawn_signing_app().await;
+    let email = format!("{}@gmail.com", Uuid::new_v4());
+    app.insert_confirmed_subscriber("default", &email).await;
+
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": "Signed newsletter",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let message = app
+        .get_delivered_newsletter("Signed newsletter", &email)
+        .expect("Newsletter was not captured");
+    let raw = String::from_utf8(general_purpose::STANDARD.decode(&message.raw).unwrap()).unwrap();
+    let raw = raw.replace("\r\n ", " ");
+    assert!(raw.contains("list-unsubscribe:list-unsubscribe-post"));
+    assert_eq!(verify_dkim(&message.raw).await, vec![DkimResult::Pass]);
+}
+
+fn urlencoding(email: &str) -> String {
+    email.replace('@', "%40")
+}
@@ -0,0 +1,292 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn create_draft(app: &TestApp, subject: &str) -> String {
+    let response = app
+        .post_draft(serde_json::json!({
+            "subject": subject,
+            "html_content": "<p>Hi {{ subscriber.name }}</p>",
+            "text_content": "Hi {{ subscriber.name }}",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn drafts_can_be_created_edited_and_deleted() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_draft(&app, "First take").await;
+
+    let response = app
+        .put_draft(
+            &newsletter_issue_id,
+            serde_json::json!({
+                "subject": "Second take",
+                "html_content": "<p>Hello {{ subscriber.name }}</p>",
+                "text_content": "  ",
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let draft: serde_json::Value = app
+        .get_draft(&newsletter_issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(draft["subject"], "Second take");
+    assert_eq!(draft["html_content"], "<p>Hello {{ subscriber.name }}</p>");
+    // A blank text part falls back to the HTML, as it does when publishing directly.
+    assert!(draft["text_content"]
+        .as_str()
+        .unwrap()
+        .contains("Hello {{ subscriber.name }}"));
+
+    let response = app.delete_draft(&newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 204);
+    let response = app.get_draft(&newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn drafts_are_listed_but_never_archived_or_delivered() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_draft(&app, "Work in progress").await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/newsletters/drafts", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let drafts: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(drafts[0]["newsletter_issue_id"], newsletter_issue_id);
+
+    let response = reqwest::get(format!(
+        "{}/newsletter/archive/{}",
+        app.address, newsletter_issue_id
+    ))
+    .await
+    .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+    let tasks = sqlx::query!("SELECT subscriber_id FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch delivery tasks");
+    assert!(tasks.is_empty());
+}
+
+#[tokio::test]
+async fn drafts_with_invalid_templates_are_rejected() {
+    let app = spawn_app().await;
+    let response = app
+        .post_draft(serde_json::json!({
+            "subject": "Broken",
+            "html_content": "<p>Hi {{ subscriber.nmae }}</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["field"], "html_content");
+}
+
+#[tokio::test]
+async fn preview_renders_the_draft_for_a_made_up_subscriber() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_draft(&app, "Preview me").await;
+
+    let response = app
+        .get_draft_preview(
+            &newsletter_issue_id,
+            &[("name", "Ursula"), ("email", "ursula@example.com")],
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let preview: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(preview["subject"], "Preview me");
+    assert!(preview["html_content"]
+        .as_str()
+        .unwrap()
+        .contains("<p>Hi Ursula</p>"));
+    assert!(preview["text_content"]
+        .as_str()
+        .unwrap()
+        .contains("Unsubscribe"));
+    assert!(preview["text_content"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/preferences?token="));
+
+    let response = app
+        .get_draft_preview(&newsletter_issue_id, &[("email", "not-an-email")])
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn preview_renders_the_draft_for_an_existing_subscriber() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_draft(&app, "Preview for a subscriber").await;
+    let subscriber_id = app
+        .insert_subscriber("default", "le_guin@example.com", "Le Guin", "confirmed")
+        .await;
+
+    let preview: serde_json::Value = app
+        .get_draft_preview(
+            &newsletter_issue_id,
+            &[("subscriber_id", &subscriber_id.to_string())],
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(preview["text_content"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hi Le Guin"));
+
+    let response = app
+        .get_draft_preview(
+            &newsletter_issue_id,
+            &[("subscriber_id", &Uuid::new_v4().to_string())],
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn test_send_only_reaches_the_given_addresses() {
+    let app = spawn_app().await;
+    let subscriber_email = format!("{}@gmail.com", Uuid::new_v4());
+    app.insert_confirmed_subscriber("default", &subscriber_email).await;
+    let subject = format!("Test send {}", Uuid::new_v4());
+    let newsletter_issue_id = create_draft(&app, &subject).await;
+    let editor_email = format!("{}@gmail.com", Uuid::new_v4());
+
+    let response = app
+        .post_draft_test_send(
+            &newsletter_issue_id,
+            serde_json::json!({"recipients": [editor_email], "name": "Editor"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let test_subject = format!("[Test] {}", subject);
+    let message = app
+        .get_delivered_newsletter(&test_subject, &editor_email)
+        .expect("Test send was not delivered");
+    assert!(message.text.contains("Hi Editor"));
+    assert!(!app.check_newsletter_delivered(&test_subject, &subscriber_email));
+    // Still a draft afterwards.
+    let response = app.get_draft(&newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn test_send_rejects_invalid_recipients() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_draft(&app, "Test send").await;
+    let too_many: Vec<String> = (0..11)
+        .map(|i| format!("editor{}@example.com", i))
+        .collect();
+    for recipients in [
+        serde_json::json!([]),
+        serde_json::json!(["not-an-email"]),
+        serde_json::json!(too_many),
+    ] {
+        let response = app
+            .post_draft_test_send(
+                &newsletter_issue_id,
+                serde_json::json!({ "recipients": recipients }),
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
+
+#[tokio::test]
+async fn published_drafts_are_delivered_and_no_longer_editable() {
+    let app = spawn_app().await;
+    let subscriber_email = format!("{}@gmail.com", Uuid::new_v4());
+    app.insert_confirmed_subscriber("default", &subscriber_email).await;
+    let subject = format!("Publish me {}", Uuid::new_v4());
+    let newsletter_issue_id = create_draft(&app, &subject).await;
+
+    let response = app
+        .post_draft_publish(&newsletter_issue_id, serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+    assert!(app.check_newsletter_delivered(&subject, &subscriber_email));
+
+    let response = app
+        .post_draft_publish(&newsletter_issue_id, serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app
+        .put_draft(
+            &newsletter_issue_id,
+            serde_json::json!({"subject": "Too late", "html_content": "<p>Too late</p>"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn publishing_with_an_unparseable_body_is_rejected_and_queues_nothing() {
+    let app = spawn_app().await;
+    app.post_subscriptions("name=testName&email=testEmail%40gmail.com")
+        .await
+        .error_for_status()
+        .expect("Failed to create subscriber");
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to confirm subscriber");
+    let newsletter_issue_id = create_draft(&app, "Not yet").await;
+
+    for send_at in ["tomorow", "2030-01-01T09:00:00", "2030-13-01T09:00:00Z"] {
+        let response = app
+            .post_draft_publish(
+                &newsletter_issue_id,
+                serde_json::json!({ "send_at": send_at }),
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 400, "send_at: {}", send_at);
+    }
+    // A body that isn't declared as JSON isn't read as an immediate publish either.
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/admin/newsletters/drafts/{}/publish",
+            &app.address, newsletter_issue_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .body(r#"{"send_at": "2030-01-01T09:00:00Z"}"#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_client_error());
+
+    let queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count queued deliveries");
+    assert_eq!(queued.count, 0);
+    let response = app.get_draft(&newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn draft_endpoints_require_authentication() {
+    let app = spawn_app().await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/newsletters/drafts", app.address))
+        .json(&serde_json::json!({"subject": "Title", "html_content": "<p>Body</p>"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -2,12 +2,18 @@ use crate::smtp_sever::*;
 use argon2::password_hash::SaltString;
 use argon2::{Argon2, PasswordHasher};
 use rust_email_newsletter::configuration::*;
-use rust_email_newsletter::email_client::ConfirmationLink;
-use rust_email_newsletter::startup::Application;
+use rust_email_newsletter::confirmation_worker::try_send_confirmation;
+use rust_email_newsletter::digest_worker::try_send_digest;
+use rust_email_newsletter::email_client::{ConfirmationLink, ConfirmationTemplates, EmailClient};
+use rust_email_newsletter::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use rust_email_newsletter::issue_scheduler::{try_publish_due_issue, SchedulerOutcome};
+use rust_email_newsletter::startup::{get_email_client, Application};
 use rust_email_newsletter::telemetry::init_subscriber;
+use secrecy::Secret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use std::collections::HashSet;
 use std::sync::{Arc, Once, OnceLock, RwLock};
+use std::time::Duration;
 use uuid::Uuid;
 
 static INIT_SUBSCRIBER: Once = Once::new();
@@ -19,21 +25,545 @@ pub struct TestApp {
     pub db_pool: PgPool,
     pub storage: Arc<RwLock<HashSet<MailMessage>>>,
     pub test_user: TestUser,
+    pub email_client: EmailClient,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    pub confirmation_templates: ConfirmationTemplates,
+    /// Keeps the session cookie between requests and leaves redirects to the test.
+    pub api_client: reqwest::Client,
 }
 
 impl TestApp {
-    pub async fn post_subscriptions(&self, body: &'static str) -> reqwest::Response {
+    pub async fn post_subscriptions(&self, body: impl Into<String>) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(body)
+            .body(body.into())
             .send()
             .await
             .expect("Failed to execute request.")
     }
     pub async fn post_newsletter(&self, body_json: serde_json::Value) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/newsletter", &self.address))
+            .post(format!("{}/newsletter", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body_json)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_newsletter_with_idempotency_key(
+        &self,
+        body_json: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletter", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body_json)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_unsubscribe(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/subscriptions/unsubscribe", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_unsubscribe(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/unsubscribe", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_preferences(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/subscriptions/preferences", &self.address))
+            .header("Accept", "application/json")
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    /// The page a subscriber opens from the link in an email.
+    pub async fn get_preferences_html(&self, token: &str) -> String {
+        self.api_client
+            .get(format!("{}/subscriptions/preferences", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn post_preferences_form(
+        &self,
+        token: &str,
+        body: &[(&str, &str)],
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/preferences", &self.address))
+            .query(&[("token", token)])
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn put_preferences(
+        &self,
+        token: &str,
+        body_json: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .put(format!("{}/subscriptions/preferences", &self.address))
+            .query(&[("token", token)])
+            .json(&body_json)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_unsubscribe_from_all(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/subscriptions/preferences/unsubscribe",
+                &self.address
+            ))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_dead_letters(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/deliveries/dead_letters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_list(&self, body_json: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/lists", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body_json)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn create_list(&self, slug: &str) {
+        let response = self
+            .post_list(serde_json::json!({"slug": slug, "name": "Rust Weekly"}))
+            .await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+    /// Inserts a subscription straight into the database, skipping the signup flow.
+    pub async fn insert_subscriber(
+        &self,
+        list: &str,
+        email: &str,
+        name: &str,
+        status: &str,
+    ) -> Uuid {
+        let subscriber_id = Uuid::new_v4();
+        sqlx::query!(
+            "INSERT INTO subscriptions (id, list_id, email, name, subscribed_at, status) VALUES ($1, (SELECT list_id FROM lists WHERE slug = $2), $3, $4, now(), $5)",
+            subscriber_id,
+            list,
+            email,
+            name,
+            status,
+        )
+        .execute(&self.db_pool)
+        .await
+        .expect("Failed to insert subscriber");
+        subscriber_id
+    }
+    pub async fn insert_confirmed_subscriber(&self, list: &str, email: &str) -> Uuid {
+        self.insert_subscriber(list, email, "testName", "confirmed").await
+    }
+    pub async fn backdate_subscriber(&self, subscriber_id: Uuid, days: i64) {
+        sqlx::query!(
+            "UPDATE subscriptions SET subscribed_at = now() - make_interval(days => $2) WHERE id = $1",
+            subscriber_id,
+            days as i32,
+        )
+        .execute(&self.db_pool)
+        .await
+        .expect("Failed to backdate subscriber");
+    }
+    pub async fn put_subscriber_tags(
+        &self,
+        subscriber_id: Uuid,
+        body_json: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .put(format!(
+                "{}/admin/subscribers/{}/tags",
+                &self.address, subscriber_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body_json)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_subscribers(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/subscribers", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    /// Logs the test user in through the form, the session sticks to `api_client`.
+    pub async fn login(&self) {
+        let response = self
+            .post_login(&serde_json::json!({
+                "username": &self.test_user.username,
+                "password": &self.test_user.password,
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_newsletter_form(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_newsletter_form_html(&self) -> String {
+        self.get_newsletter_form().await.text().await.unwrap()
+    }
+    pub async fn post_newsletter_form<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/password", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_change_password_html(&self) -> String {
+        self.get_change_password().await.text().await.unwrap()
+    }
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/password", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_users(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/users", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_users(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/users", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn patch_user(&self, user_id: &str, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!("{}/admin/users/{}", &self.address, user_id))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn delete_user(&self, user_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!("{}/admin/users/{}", &self.address, user_id))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_api_tokens(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/tokens", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_api_tokens(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/tokens", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn delete_api_token(&self, token_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!("{}/admin/tokens/{}", &self.address, token_id))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_subscriber_export(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/subscribers/export", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    /// Uploads `csv` as the `file` of a multipart form next to the text `fields`.
+    pub async fn post_subscriber_import(
+        &self,
+        fields: &[(&str, &str)],
+        csv: &str,
+    ) -> reqwest::Response {
+        let boundary = Uuid::new_v4().to_string();
+        let mut body = String::new();
+        for (name, value) in fields {
+            body.push_str(&format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            ));
+        }
+        body.push_str(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"subscribers.csv\"\r\nContent-Type: text/csv\r\n\r\n{}\r\n--{}--\r\n",
+            boundary, csv, boundary
+        ));
+        reqwest::Client::new()
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/subscribers/{}", &self.address, subscriber_id))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn patch_subscriber(
+        &self,
+        subscriber_id: Uuid,
+        body_json: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!("{}/admin/subscribers/{}", &self.address, subscriber_id))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body_json)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn delete_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!("{}/admin/subscribers/{}", &self.address, subscriber_id))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_scheduled_issues(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/newsletters/scheduled", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn patch_scheduled_issue(
+        &self,
+        newsletter_issue_id: &str,
+        body_json: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!(
+                "{}/admin/newsletters/scheduled/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body_json)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn delete_scheduled_issue(&self, newsletter_issue_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!(
+                "{}/admin/newsletters/scheduled/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_draft(&self, body_json: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/newsletters/drafts", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body_json)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_draft(&self, newsletter_issue_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn put_draft(
+        &self,
+        newsletter_issue_id: &str,
+        body_json: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .put(format!(
+                "{}/admin/newsletters/drafts/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body_json)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn delete_draft(&self, newsletter_issue_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!(
+                "{}/admin/newsletters/drafts/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_draft_preview(
+        &self,
+        newsletter_issue_id: &str,
+        query: &[(&str, &str)],
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}/preview",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_draft_test_send(
+        &self,
+        newsletter_issue_id: &str,
+        body_json: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/test_send",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body_json)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_draft_publish(
+        &self,
+        newsletter_issue_id: &str,
+        body_json: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/publish",
+                &self.address, newsletter_issue_id
+            ))
             .basic_auth(&self.test_user.username, Some(&self.test_user.password))
             .json(&body_json)
             .send()
@@ -49,11 +579,118 @@ impl TestApp {
         for message in (*self.storage.read().expect("Cannot read from storage")).iter() {
             let mut trim_message = message.html.clone();
             trim_message.retain(|c| c.is_ascii_graphic());
-            if trim_message == html_body {
+            if trim_message.starts_with(&html_body) {
                 return true;
             }
         }
-        return false;
+        false
+    }
+    pub fn check_newsletter_delivered(&self, subject: &str, recipent_mail: &str) -> bool {
+        self.get_delivered_newsletter(subject, recipent_mail)
+            .is_some()
+    }
+    pub fn get_delivered_newsletter(&self, subject: &str, recipent_mail: &str) -> Option<MailMessage> {
+        (*self.storage.read().expect("Cannot read from storage"))
+            .iter()
+            .find(|message| {
+                message.subject == subject
+                    && message
+                        .envelope_recipients
+                        .iter()
+                        .any(|recipent| recipent.contains(recipent_mail))
+            })
+            .cloned()
+    }
+    pub async fn publish_due_issues(&self) {
+        loop {
+            let outcome = try_publish_due_issue(&self.db_pool)
+                .await
+                .expect("Failed to publish scheduled issue");
+            if let SchedulerOutcome::NothingDue = outcome {
+                return;
+            }
+        }
+    }
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            let outcome = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.hmac_secret,
+            )
+            .await
+                .expect("Failed to execute delivery task");
+            if let ExecutionOutcome::EmptyQueue = outcome {
+                // The background worker may still hold a task we skipped over.
+                let pending = sqlx::query!(
+                    "SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue WHERE status = 'pending' AND execute_after <= now()"
+                )
+                .fetch_one(&self.db_pool)
+                .await
+                .expect("Failed to count pending delivery tasks");
+                if pending.count == 0 {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+    }
+    pub async fn dispatch_all_confirmation_emails(&self) {
+        loop {
+            let outcome = try_send_confirmation(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.hmac_secret,
+                &self.confirmation_templates,
+            )
+            .await
+            .expect("Failed to send confirmation email");
+            if let ExecutionOutcome::EmptyQueue = outcome {
+                // The background worker may still hold a task we skipped over.
+                let pending = sqlx::query!(
+                    "SELECT COUNT(*) AS \"count!\" FROM confirmation_email_queue WHERE execute_after <= now()"
+                )
+                .fetch_one(&self.db_pool)
+                .await
+                .expect("Failed to count queued confirmation emails");
+                if pending.count == 0 {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+    }
+    /// Brings every held back digest task forward and sends the digests they make up.
+    pub async fn send_all_digests_now(&self) {
+        sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now() WHERE status = 'digest'")
+            .execute(&self.db_pool)
+            .await
+            .expect("Failed to bring digest tasks forward");
+        loop {
+            let outcome = try_send_digest(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.hmac_secret,
+            )
+            .await
+            .expect("Failed to send digest");
+            if let ExecutionOutcome::EmptyQueue = outcome {
+                // The background worker may still hold a digest we skipped over.
+                let pending = sqlx::query!(
+                    "SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue WHERE status = 'digest' AND execute_after <= now()"
+                )
+                .fetch_one(&self.db_pool)
+                .await
+                .expect("Failed to count due digest tasks");
+                if pending.count == 0 {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
     }
 }
 
@@ -70,7 +707,7 @@ impl TestUser {
             password: Uuid::new_v4().to_string(),
         }
     }
-    async fn store(&self, pool: &PgPool) {
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::default()
             .hash_password(self.password.as_bytes(), &salt)
@@ -78,7 +715,7 @@ impl TestUser {
             .to_string();
 
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, password_hash, is_superuser) VALUES ($1, $2, $3, true)",
             self.user_id,
             self.username,
             password_hash,
@@ -89,7 +726,23 @@ impl TestUser {
     }
 }
 
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with_email_client(|configuration| {
+        get_email_client(configuration).expect("Failed to build email client")
+    })
+    .await
+}
+
+/// Spawns the application delivering through the client built by `build_email_client`,
+/// e.g. one backed by an `InMemoryTransport` the test keeps a handle to.
+pub async fn spawn_app_with_email_client(
+    build_email_client: impl FnOnce(&Settings) -> EmailClient,
+) -> TestApp {
     if std::env::var("TEST_LOG").is_ok() {
         INIT_SUBSCRIBER.call_once(|| init_subscriber("email_newsletter", "error", std::io::stdout));
     } else {
@@ -105,18 +758,16 @@ pub async fn spawn_app() -> TestApp {
     let storage = STORAGE.get_or_init(|| Arc::new(RwLock::new(HashSet::default())));
 
     OPEN_SMTP_SEVER.call_once(|| {
-        open_smtp_sever(
-            (
-                configuration.smtp_sever.smtp_host,
-                configuration.smtp_sever.smtp_port,
-            ),
-            storage.clone(),
-        )
-        .expect("Failed to start SMTP sever");
+        let EmailTransportSettings::Smtp(smtp) = &configuration.email_client.transport else {
+            panic!("Tests expect an SMTP transport pointing at the embedded sever");
+        };
+        open_smtp_sever((smtp.host.as_str(), smtp.port), storage.clone())
+            .expect("Failed to start SMTP sever");
     });
 
     let db_pool = config_database(&configuration.database).await;
-    let application = Application::build(&configuration)
+    let email_client = build_email_client(&configuration);
+    let application = Application::build_with_email_client(&configuration, email_client.clone())
         .await
         .expect("Failed to build application");
     let address = format!("http://127.0.0.1:{}", application.port());
@@ -126,6 +777,19 @@ pub async fn spawn_app() -> TestApp {
         db_pool,
         storage: storage.clone(),
         test_user: TestUser::generate(),
+        email_client,
+        base_url: configuration.application.base_url.clone(),
+        hmac_secret: configuration.application.hmac_secret.clone(),
+        confirmation_templates: ConfirmationTemplates::load(
+            &configuration.email_client.templates_directory,
+            &configuration.email_client.default_locale,
+        )
+        .expect("Failed to load confirmation templates"),
+        api_client: reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .build()
+            .unwrap(),
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -0,0 +1,172 @@
+use rust_email_newsletter::domain::UnsubscribeToken;
+use rust_email_newsletter::email_client::EmailClient;
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn get_status(app: &TestApp, list: &str, email: &str) -> String {
+    sqlx::query!(
+        "SELECT s.status FROM subscriptions s JOIN lists l ON l.list_id = s.list_id WHERE l.slug = $1 AND s.email = $2",
+        list,
+        email,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription")
+    .status
+}
+
+#[tokio::test]
+async fn lists_can_be_created_and_listed() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/lists", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let lists: serde_json::Value = response.json().await.unwrap();
+    let slugs: Vec<&str> = lists
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|list| list["slug"].as_str().unwrap())
+        .collect();
+    assert_eq!(slugs, vec!["default", "rust-weekly"]);
+}
+
+#[tokio::test]
+async fn duplicate_or_invalid_lists_are_rejected() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let test_cases = [
+        (
+            serde_json::json!({"slug": "rust-weekly", "name": "Again"}),
+            409,
+        ),
+        (
+            serde_json::json!({"slug": "Rust Weekly", "name": "Rust Weekly"}),
+            400,
+        ),
+        (serde_json::json!({"slug": "rust-daily", "name": " "}), 400),
+    ];
+    for (body, expected_status) in test_cases {
+        let response = app.post_list(body).await;
+        assert_eq!(response.status().as_u16(), expected_status);
+    }
+}
+
+#[tokio::test]
+async fn the_same_email_can_subscribe_to_several_lists() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let email = format!("{}@gmail.com", Uuid::new_v4());
+    let body = format!("name=testName&email={}", email);
+
+    let response = app.post_subscriptions(body.clone()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_subscriptions(format!("{}&list=rust-weekly", body))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(
+        get_status(&app, "default", &email).await,
+        "pending_confirmation"
+    );
+    assert_eq!(
+        get_status(&app, "rust-weekly", &email).await,
+        "pending_confirmation"
+    );
+}
+
+#[tokio::test]
+async fn confirmation_only_applies_to_its_own_list() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let email = format!("{}@gmail.com", Uuid::new_v4());
+    let body = format!("name=testName&email={}", email);
+    app.post_subscriptions(body.clone()).await;
+    app.post_subscriptions(format!("{}&list=rust-weekly", body))
+        .await;
+
+    let token = sqlx::query!(
+        "SELECT t.subscription_token FROM subscription_tokens t JOIN subscriptions s ON s.id = t.subscriber_id JOIN lists l ON l.list_id = s.list_id WHERE l.slug = 'rust-weekly'",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch subscription token")
+    .subscription_token;
+    let response = reqwest::get(EmailClient::get_confirmation_link(&app.address, &token).0)
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(get_status(&app, "rust-weekly", &email).await, "confirmed");
+    assert_eq!(
+        get_status(&app, "default", &email).await,
+        "pending_confirmation"
+    );
+}
+
+#[tokio::test]
+async fn unsubscribing_only_leaves_that_list() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let email = format!("{}@gmail.com", Uuid::new_v4());
+    app.insert_confirmed_subscriber("default", &email).await;
+    let subscriber_id = app.insert_confirmed_subscriber("rust-weekly", &email).await;
+
+    let token = UnsubscribeToken::generate(subscriber_id, &app.hmac_secret);
+    let response = app.post_unsubscribe(token.as_ref()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(
+        get_status(&app, "rust-weekly", &email).await,
+        "unsubscribed"
+    );
+    assert_eq!(get_status(&app, "default", &email).await, "confirmed");
+}
+
+#[tokio::test]
+async fn newsletters_only_reach_the_targeted_list() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let default_email = format!("{}@gmail.com", Uuid::new_v4());
+    let weekly_email = format!("{}@gmail.com", Uuid::new_v4());
+    app.insert_confirmed_subscriber("default", &default_email).await;
+    app.insert_confirmed_subscriber("rust-weekly", &weekly_email).await;
+
+    let subject = format!("Weekly {}", Uuid::new_v4());
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "html_content": "<p>This week in Rust</p>",
+            "list": "rust-weekly",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    assert!(app.check_newsletter_delivered(&subject, &weekly_email));
+    assert!(!app.check_newsletter_delivered(&subject, &default_email));
+}
+
+#[tokio::test]
+async fn unknown_lists_are_rejected_with_404() {
+    let app = spawn_app().await;
+    let response = app
+        .post_subscriptions("name=testName&email=ursula%40gmail.com&list=nope")
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": "Nowhere",
+            "html_content": "<p>Nowhere</p>",
+            "list": "nope",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
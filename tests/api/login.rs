@@ -0,0 +1,57 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn an_error_flash_message_is_set_on_failure() {
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(r#"<p class="error"><i>Authentication failed</i></p>"#));
+
+    // The message is gone once it has been shown.
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("Authentication failed"));
+}
+
+#[tokio::test]
+async fn a_wrong_password_is_rejected() {
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn sessions_are_stored_in_the_database() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let saved = sqlx::query!("SELECT state FROM sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved session");
+    assert!(saved
+        .state
+        .to_string()
+        .contains(&app.test_user.user_id.to_string()));
+}
@@ -2,5 +2,22 @@ mod helpers;
 mod health_check;
 mod subscriptions;
 mod subscription_confimation;
+mod subscriptions_unsubscribe;
+mod subscriptions_preferences;
+mod lists;
+mod segments;
+mod subscribers;
+mod subscriber_import;
+mod subscriber_export;
 mod newsletter;
+mod login;
+mod admin_dashboard;
+mod newsletter_form;
+mod change_password;
+mod users;
+mod api_tokens;
+mod drafts;
+mod scheduled_issues;
+mod digests;
+mod dkim;
 mod smtp_sever;
\ No newline at end of file
@@ -1,16 +1,18 @@
-use crate::helpers::{spawn_app, TestApp};
+use crate::helpers::{spawn_app, TestApp, TestUser};
 use rust_email_newsletter::email_client::{ConfirmationLink, EmailClient};
 use uuid::Uuid;
 
 async fn create_unconfirm_subscriber(app: &TestApp) -> ConfirmationLink {
     let body = "name=testName&email=testEmail%40gmail.com";
-    let response = app
-        .post_subscriptions(body)
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .expect("Failed to create subscriber");
-    let subscription_token = response.text().await.expect("Failed to get response");
-    EmailClient::get_confirmation_link(&app.address, &subscription_token)
+    let saved = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved token");
+    EmailClient::get_confirmation_link(&app.address, &saved.subscription_token)
 }
 async fn create_confirm_subscriber(app: &TestApp) {
     let confimation_link = create_unconfirm_subscriber(app).await;
@@ -25,7 +27,7 @@ async fn create_confirm_subscriber(app: &TestApp) {
 async fn requests_missing_authorization_are_rejected() {
     let app = spawn_app().await;
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletter", &app.address))
+        .post(format!("{}/newsletter", &app.address))
         .json(&serde_json::json!({
             "subject": "Newsletter title",
             "content": "Newsletter body as plain text",
@@ -42,6 +44,27 @@ async fn requests_missing_authorization_are_rejected() {
 
 #[tokio::test]
 async fn newsletter_are_delivered_to_confirmed_subscriber() {
+    let app = spawn_app().await;
+    create_confirm_subscriber(&app).await;
+    let subject = Uuid::new_v4().to_string();
+    let newsletter_request_body = serde_json::json!({
+        "subject": subject,
+        "content": "<p>Newsletter body as HTML</p>",
+    });
+    let response = app.post_newsletter(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.dispatch_all_pending_emails().await;
+    assert!(app.check_newsletter_delivered(&subject, "testEmail@gmail.com"));
+    let task = sqlx::query!("SELECT status, delivered_at FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch delivery task");
+    assert_eq!(task.status, "delivered");
+    assert!(task.delivered_at.is_some());
+}
+#[tokio::test]
+async fn newsletter_is_persisted_before_delivery() {
     let app = spawn_app().await;
     create_confirm_subscriber(&app).await;
     let newsletter_request_body = serde_json::json!({
@@ -49,7 +72,21 @@ async fn newsletter_are_delivered_to_confirmed_subscriber() {
         "content": "<p>Newsletter body as HTML</p>",
     });
     let response = app.post_newsletter(newsletter_request_body).await;
-    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 202);
+
+    let issue = sqlx::query!("SELECT newsletter_issue_id, title FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved issue");
+    assert_eq!(issue.title, "Newsletter title");
+    let tasks = sqlx::query!(
+        "SELECT subscriber_id FROM issue_delivery_queue WHERE newsletter_issue_id = $1",
+        issue.newsletter_issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch delivery tasks");
+    assert_eq!(tasks.len(), 1);
 }
 #[tokio::test]
 async fn newsletters_returns_400_for_invalid_data() {
@@ -84,7 +121,7 @@ async fn non_existing_user_is_rejected() {
     let username = Uuid::new_v4().to_string();
     let password = Uuid::new_v4().to_string();
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletter", &app.address))
+        .post(format!("{}/newsletter", &app.address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({
             "subject": "Newsletter title",
@@ -106,7 +143,7 @@ async fn invalid_password_is_rejected() {
     let password = Uuid::new_v4().to_string();
     assert_ne!(app.test_user.password, password);
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletter", &app.address))
+        .post(format!("{}/newsletter", &app.address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({
             "subject": "Newsletter title",
@@ -121,3 +158,323 @@ async fn invalid_password_is_rejected() {
         response.headers()["WWW-Authenticate"]
     );
 }
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    let app = spawn_app().await;
+    create_confirm_subscriber(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "subject": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletter_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let response = app
+        .post_newsletter_with_idempotency_key(newsletter_request_body, &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved issues");
+    assert_eq!(issues.len(), 1);
+    let tasks = sqlx::query!("SELECT subscriber_id FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch delivery tasks");
+    assert_eq!(tasks.len(), 1);
+}
+#[tokio::test]
+async fn concurrent_newsletter_submission_is_handled_gracefully() {
+    let app = spawn_app().await;
+    create_confirm_subscriber(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "subject": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+    let (response1, response2) = tokio::join!(
+        app.post_newsletter_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key),
+        app.post_newsletter_with_idempotency_key(newsletter_request_body, &idempotency_key),
+    );
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(response1.status().as_u16(), 202);
+
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved issues");
+    assert_eq!(issues.len(), 1);
+}
+#[tokio::test]
+async fn idempotency_keys_are_scoped_per_user() {
+    let app = spawn_app().await;
+    let other_user = TestUser::generate();
+    other_user.store(&app.db_pool).await;
+    let newsletter_request_body = serde_json::json!({
+        "subject": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletter_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletter", &app.address))
+        .basic_auth(&other_user.username, Some(&other_user.password))
+        .header("Idempotency-Key", &idempotency_key)
+        .json(&newsletter_request_body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 202);
+
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved issues");
+    assert_eq!(issues.len(), 2);
+}
+#[tokio::test]
+async fn permanent_delivery_failures_are_dead_lettered() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("default", "permanent-failure@example.com").await;
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.dispatch_all_pending_emails().await;
+    let task = sqlx::query!("SELECT status, n_attempts, last_error FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch delivery task");
+    assert_eq!(task.status, "dead_letter");
+    assert_eq!(task.n_attempts, 1);
+    assert!(task.last_error.is_some());
+
+    let response = app.get_dead_letters().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let dead_letters: serde_json::Value = response.json().await.expect("Failed to parse body");
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+    assert_eq!(
+        dead_letters[0]["subscriber_email"],
+        "permanent-failure@example.com"
+    );
+}
+#[tokio::test]
+async fn transient_delivery_failures_are_retried_later() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("default", "transient-failure@example.com").await;
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.dispatch_all_pending_emails().await;
+    let task = sqlx::query!(
+        "SELECT status, n_attempts, execute_after > now() AS \"scheduled_later!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch delivery task");
+    assert_eq!(task.status, "pending");
+    assert_eq!(task.n_attempts, 1);
+    assert!(task.scheduled_later);
+
+    let response = app.get_dead_letters().await;
+    let dead_letters: serde_json::Value = response.json().await.expect("Failed to parse body");
+    assert!(dead_letters.as_array().unwrap().is_empty());
+}
+#[tokio::test]
+async fn dead_letters_require_authorization() {
+    let app = spawn_app().await;
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/deliveries/dead_letters", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(401, response.status().as_u16());
+}
+#[tokio::test]
+async fn newsletters_are_sent_as_multipart_alternative() {
+    let app = spawn_app().await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    app.insert_confirmed_subscriber("default", &email).await;
+    let subject = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let message = app
+        .get_delivered_newsletter(&subject, &email)
+        .expect("Newsletter was not delivered");
+    assert!(message.html.contains("<p>Newsletter body as HTML</p>"));
+    assert!(message.text.starts_with("Newsletter body as plain text"));
+    assert!(!message.text.contains("<p>"));
+}
+#[tokio::test]
+async fn plain_text_part_is_generated_when_omitted() {
+    let app = spawn_app().await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    app.insert_confirmed_subscriber("default", &email).await;
+    let subject = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "html_content": "<h1>Title</h1><p>Newsletter body as <b>HTML</b></p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let issue = sqlx::query!("SELECT text_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved issue");
+    assert!(issue.text_content.contains("Title"));
+    assert!(issue.text_content.contains("Newsletter body as"));
+    assert!(!issue.text_content.contains("<p>"));
+
+    app.dispatch_all_pending_emails().await;
+    let message = app
+        .get_delivered_newsletter(&subject, &email)
+        .expect("Newsletter was not delivered");
+    assert!(message.text.contains("Newsletter body as"));
+}
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    let app = spawn_app().await;
+    create_unconfirm_subscriber(&app).await;
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let tasks = sqlx::query!("SELECT subscriber_id FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch delivery tasks");
+    assert!(tasks.is_empty());
+}
+#[tokio::test]
+async fn newsletters_are_personalized_for_each_subscriber() {
+    let app = spawn_app().await;
+    let recipients = [
+        ("Ursula", format!("{}@gmail.com", Uuid::new_v4())),
+        ("Ferris", format!("{}@gmail.com", Uuid::new_v4())),
+    ];
+    for (name, email) in &recipients {
+        app.insert_subscriber("default", email, name, "confirmed").await;
+    }
+    let subject = format!("Personalized {}", Uuid::new_v4());
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "html_content": "<p>Hi {{ subscriber.name }}, this went to {{ subscriber.email }}.</p><a href=\"{{ archive_url }}\">Read online</a>",
+            "text_content": "Hi {{ subscriber.name }}, read online at {{ archive_url }}",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let newsletter_issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch newsletter issue")
+        .newsletter_issue_id;
+    let archive_link = EmailClient::get_archive_link(&app.base_url, newsletter_issue_id);
+    for (name, email) in &recipients {
+        let message = app
+            .get_delivered_newsletter(&subject, email)
+            .expect("Newsletter was not delivered");
+        assert!(message.html.contains(&format!("Hi {}, this went to {}.", name, email)));
+        assert!(message.html.contains(&archive_link.0));
+        assert!(message
+            .text
+            .contains(&format!("Hi {}, read online at {}", name, archive_link.0)));
+    }
+}
+#[tokio::test]
+async fn newsletters_with_template_syntax_errors_are_rejected() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("default", "testEmail@gmail.com").await;
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": "Newsletter title",
+            "html_content": "<p>Hello</p>\n<p>{{ subscriber.name </p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["field"], "html_content");
+    assert_eq!(body["line"], 2);
+
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch newsletter issues");
+    assert!(issues.is_empty());
+}
+#[tokio::test]
+async fn newsletters_using_unknown_variables_are_rejected() {
+    let app = spawn_app().await;
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": "Newsletter title",
+            "html_content": "<p>Hello</p>",
+            "text_content": "Hello\n{{ subscriber.nmae }}",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["field"], "text_content");
+    assert_eq!(body["line"], 2);
+}
+#[tokio::test]
+async fn archived_issues_are_publicly_readable() {
+    let app = spawn_app().await;
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": "Archived title",
+            "html_content": "<p>Hi {{ subscriber.name }}, welcome to the archive</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let newsletter_issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch newsletter issue")
+        .newsletter_issue_id;
+
+    let response = reqwest::get(format!("{}/newsletter/archive/{}", app.address, newsletter_issue_id))
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<title>Archived title</title>"));
+    assert!(body.contains("<p>Hi , welcome to the archive</p>"));
+
+    let response = reqwest::get(format!("{}/newsletter/archive/{}", app.address, Uuid::new_v4()))
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+}
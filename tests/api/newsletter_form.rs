@@ -0,0 +1,86 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_newsletter_form() {
+    let app = spawn_app().await;
+    let response = app.get_newsletter_form().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_publish_a_newsletter() {
+    let app = spawn_app().await;
+    let response = app
+        .post_newsletter_form(&serde_json::json!({
+            "subject": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_form_offers_every_list() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains(r#"<option value="default" selected>"#));
+}
+
+#[tokio::test]
+async fn publishing_from_the_form_creates_the_issue_once() {
+    let app = spawn_app().await;
+    app.login().await;
+    let subject = Uuid::new_v4().to_string();
+    let body = serde_json::json!({
+        "subject": &subject,
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "",
+        "list": "default",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+
+    let response = app.post_newsletter_form(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains(
+        r#"<p class="info"><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"#
+    ));
+
+    // Submitting the same form again is a no-op.
+    let response = app.post_newsletter_form(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let saved = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM newsletter_issues WHERE title = $1",
+        subject
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to count issues");
+    assert_eq!(saved.count, 1);
+}
+
+#[tokio::test]
+async fn an_unknown_list_is_flashed_as_an_error() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .post_newsletter_form(&serde_json::json!({
+            "subject": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "",
+            "list": "no-such-list",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains(r#"<p class="error">"#));
+}
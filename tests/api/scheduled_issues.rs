@@ -0,0 +1,170 @@
+use crate::helpers::{spawn_app, TestApp};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+async fn insert_confirmed_subscriber(app: &TestApp) -> String {
+    let email = format!("{}@gmail.com", Uuid::new_v4());
+    app.insert_confirmed_subscriber("default", &email).await;
+    email
+}
+
+async fn schedule_issue(app: &TestApp, subject: &str) -> String {
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "html_content": "<p>Scheduled body</p>",
+            "send_at": Utc::now() + Duration::hours(1),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["send_at"].is_string());
+    body["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_sent_right_away() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app).await;
+    schedule_issue(&app, "Later").await;
+
+    let tasks = sqlx::query!("SELECT subscriber_id FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch delivery tasks");
+    assert!(tasks.is_empty());
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch newsletter issue");
+    assert_eq!(issue.status, "scheduled");
+    assert!(issue.published_at.is_none());
+}
+
+#[tokio::test]
+async fn send_at_in_the_past_publishes_immediately() {
+    let app = spawn_app().await;
+    let email = insert_confirmed_subscriber(&app).await;
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": "Overdue",
+            "html_content": "<p>Overdue body</p>",
+            "send_at": Utc::now() - Duration::minutes(5),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+    assert!(app.check_newsletter_delivered("Overdue", &email));
+}
+
+#[tokio::test]
+async fn due_scheduled_issues_are_published_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+    let email = insert_confirmed_subscriber(&app).await;
+    let subject = format!("Scheduled {}", Uuid::new_v4());
+    let newsletter_issue_id = schedule_issue(&app, &subject).await;
+
+    // Subscribers who confirm after scheduling still get the issue.
+    let late_email = insert_confirmed_subscriber(&app).await;
+    let response = app
+        .patch_scheduled_issue(
+            &newsletter_issue_id,
+            serde_json::json!({"send_at": Utc::now() - Duration::seconds(1)}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    assert!(app.check_newsletter_delivered(&subject, &email));
+    assert!(app.check_newsletter_delivered(&subject, &late_email));
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch newsletter issue");
+    assert_eq!(issue.status, "published");
+    assert!(issue.published_at.is_some());
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_listed_and_rescheduled() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = schedule_issue(&app, "Listed").await;
+
+    let response = app.get_scheduled_issues().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["newsletter_issue_id"], newsletter_issue_id);
+    assert_eq!(body[0]["title"], "Listed");
+
+    let send_at = "2099-01-01T09:00:00Z";
+    let response = app
+        .patch_scheduled_issue(
+            &newsletter_issue_id,
+            serde_json::json!({"send_at": send_at}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let issue = sqlx::query!("SELECT send_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch newsletter issue");
+    assert_eq!(
+        issue.send_at.unwrap().to_rfc3339(),
+        "2099-01-01T09:00:00+00:00"
+    );
+}
+
+#[tokio::test]
+async fn cancelled_issues_are_never_sent() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app).await;
+    let newsletter_issue_id = schedule_issue(&app, "Cancelled").await;
+
+    let response = app.delete_scheduled_issue(&newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 204);
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to move send_at");
+    app.publish_due_issues().await;
+
+    let tasks = sqlx::query!("SELECT subscriber_id FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch delivery tasks");
+    assert!(tasks.is_empty());
+    let body: serde_json::Value = app.get_scheduled_issues().await.json().await.unwrap();
+    assert!(body.as_array().unwrap().is_empty());
+
+    // Once cancelled there is nothing left to reschedule or cancel.
+    let response = app
+        .patch_scheduled_issue(
+            &newsletter_issue_id,
+            serde_json::json!({"send_at": Utc::now()}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app.delete_scheduled_issue(&newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn scheduled_issue_endpoints_require_authentication() {
+    let app = spawn_app().await;
+    let response = reqwest::get(format!("{}/admin/newsletters/scheduled", app.address))
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+    let response = reqwest::Client::new()
+        .delete(format!(
+            "{}/admin/newsletters/scheduled/{}",
+            app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,190 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn insert_confirmed_subscriber(app: &TestApp, subscribed_days_ago: i64) -> (Uuid, String) {
+    let email = format!("{}@gmail.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    app.backdate_subscriber(subscriber_id, subscribed_days_ago).await;
+    (subscriber_id, email)
+}
+
+async fn tag(app: &TestApp, subscriber_id: Uuid, tags: &[&str]) {
+    let response = app
+        .put_subscriber_tags(subscriber_id, serde_json::json!({ "tags": tags }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn tags_can_be_replaced_listed_and_removed() {
+    let app = spawn_app().await;
+    let (subscriber_id, _) = insert_confirmed_subscriber(&app, 0).await;
+
+    let response = app
+        .put_subscriber_tags(subscriber_id, serde_json::json!({"tags": ["Rust", "go"]}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let tags: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(tags, serde_json::json!(["go", "rust"]));
+
+    let client = reqwest::Client::new();
+    let tag_url = format!("{}/admin/subscribers/{}/tags", app.address, subscriber_id);
+    let response = client
+        .delete(format!("{}/go", tag_url))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 204);
+    let tags: serde_json::Value = client
+        .get(&tag_url)
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(tags, serde_json::json!(["rust"]));
+}
+
+#[tokio::test]
+async fn tags_of_unknown_subscribers_are_404() {
+    let app = spawn_app().await;
+    let response = app
+        .put_subscriber_tags(Uuid::new_v4(), serde_json::json!({"tags": ["rust"]}))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected() {
+    let app = spawn_app().await;
+    let (subscriber_id, _) = insert_confirmed_subscriber(&app, 0).await;
+    let response = app
+        .put_subscriber_tags(subscriber_id, serde_json::json!({"tags": ["not a tag"]}))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_subscriptions("name=testName&email=ursula%40gmail.com&tags=rust,c%2B%2B")
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn tags_can_be_chosen_at_signup() {
+    let app = spawn_app().await;
+    let response = app
+        .post_subscriptions("name=testName&email=ursula%40gmail.com&tags=Rust,%20beginner")
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let tags = sqlx::query!("SELECT tag FROM subscription_tags ORDER BY tag")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch tags");
+    let tags: Vec<String> = tags.into_iter().map(|r| r.tag).collect();
+    assert_eq!(tags, vec!["beginner", "rust"]);
+}
+
+#[tokio::test]
+async fn segmented_newsletters_only_reach_matching_subscribers() {
+    let app = spawn_app().await;
+    let (rustacean, rustacean_email) = insert_confirmed_subscriber(&app, 0).await;
+    let (beginner, beginner_email) = insert_confirmed_subscriber(&app, 0).await;
+    let (_, untagged_email) = insert_confirmed_subscriber(&app, 0).await;
+    tag(&app, rustacean, &["rust"]).await;
+    tag(&app, beginner, &["rust", "beginner"]).await;
+
+    let subject = format!("Advanced Rust {}", Uuid::new_v4());
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "html_content": "<p>Lifetimes, again</p>",
+            "segment": {"and": [{"tag": "rust"}, {"not": {"tag": "beginner"}}]},
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    assert!(app.check_newsletter_delivered(&subject, &rustacean_email));
+    assert!(!app.check_newsletter_delivered(&subject, &beginner_email));
+    assert!(!app.check_newsletter_delivered(&subject, &untagged_email));
+}
+
+#[tokio::test]
+async fn segments_can_target_recent_subscribers() {
+    let app = spawn_app().await;
+    let (_, old_email) = insert_confirmed_subscriber(&app, 30).await;
+    let (tagged, tagged_old_email) = insert_confirmed_subscriber(&app, 30).await;
+    let (_, new_email) = insert_confirmed_subscriber(&app, 0).await;
+    tag(&app, tagged, &["vip"]).await;
+
+    let subject = format!("Welcome {}", Uuid::new_v4());
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "html_content": "<p>Welcome aboard</p>",
+            "segment": {"or": [
+                {"subscribed_after": Utc::now() - Duration::days(1)},
+                {"tag": "vip"},
+            ]},
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    assert!(app.check_newsletter_delivered(&subject, &new_email));
+    assert!(app.check_newsletter_delivered(&subject, &tagged_old_email));
+    assert!(!app.check_newsletter_delivered(&subject, &old_email));
+}
+
+#[tokio::test]
+async fn scheduled_issues_keep_their_segment() {
+    let app = spawn_app().await;
+    let (rustacean, rustacean_email) = insert_confirmed_subscriber(&app, 0).await;
+    let (_, untagged_email) = insert_confirmed_subscriber(&app, 0).await;
+    tag(&app, rustacean, &["rust"]).await;
+
+    let subject = format!("Scheduled for Rust {}", Uuid::new_v4());
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "html_content": "<p>Later</p>",
+            "segment": {"tag": "rust"},
+            "send_at": Utc::now() + Duration::hours(1),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to move send_at");
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    assert!(app.check_newsletter_delivered(&subject, &rustacean_email));
+    assert!(!app.check_newsletter_delivered(&subject, &untagged_email));
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected() {
+    let app = spawn_app().await;
+    for segment in [
+        serde_json::json!({"xor": []}),
+        serde_json::json!({"tag": "not a tag"}),
+        serde_json::json!({"subscribed_after": "yesterday"}),
+    ] {
+        let response = app
+            .post_newsletter(serde_json::json!({
+                "subject": "Segmented",
+                "html_content": "<p>Segmented</p>",
+                "segment": segment,
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
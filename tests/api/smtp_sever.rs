@@ -88,7 +88,7 @@ impl TryFrom<mail_parser::Message<'_>> for MailMessage {
         };
         let to = match message
             .from()
-            .and_then(|address| Some(address.clone().into_list()))
+            .map(|address| address.clone().into_list())
         {
             Some(addr) => addr
                 .iter()
@@ -196,6 +196,19 @@ impl mailin_embedded::Handler for MailHandler {
         mailin_embedded::response::OK
     }
     fn rcpt(&mut self, to: &str) -> mailin_embedded::Response {
+        // Magic recipients let tests exercise both classes of SMTP failure.
+        if to.starts_with("permanent-failure") {
+            return mailin_embedded::response::Response::custom(
+                550,
+                "Mailbox unavailable".to_string(),
+            );
+        }
+        if to.starts_with("transient-failure") {
+            return mailin_embedded::response::Response::custom(
+                451,
+                "Try again later".to_string(),
+            );
+        }
         self.envelope_recipients.push(to.to_string());
         mailin_embedded::response::OK
     }
@@ -230,6 +243,7 @@ pub fn open_smtp_sever<A: ToSocketAddrs>(
     let name = env!("CARGO_PKG_NAME");
     server
         .with_name(name)
+        .with_num_threads(64)
         .with_ssl(SslConfig::None)?
         .with_addr(addr)?;
     std::thread::spawn(|| {
@@ -0,0 +1,109 @@
+use crate::helpers::{spawn_app, TestApp};
+
+async fn import(app: &TestApp, csv: &str, skip_confirmation: &str) {
+    let response = app
+        .post_subscriber_import(
+            &[
+                ("consent_source", "import"),
+                ("skip_confirmation", skip_confirmation),
+            ],
+            csv,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn every_subscriber_is_exported_as_csv() {
+    let app = spawn_app().await;
+    import(
+        &app,
+        "email,name\nursula@gmail.com,\"Le Guin, Ursula\"\nterry@gmail.com,Terry\n",
+        "true",
+    )
+    .await;
+
+    let response = app.get_subscriber_export(&[]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "text/csv");
+    let body = response.text().await.unwrap();
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let headers = reader.headers().unwrap().clone();
+    assert_eq!(&headers[2], "email");
+    assert_eq!(&headers[3], "name");
+    let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(&records[0][2], "ursula@gmail.com");
+    assert_eq!(&records[0][3], "Le Guin, Ursula");
+    assert_eq!(&records[0][6], "import");
+}
+
+#[tokio::test]
+async fn imported_subscribers_are_exported_in_file_order() {
+    let app = spawn_app().await;
+    let emails = [
+        "terry@gmail.com",
+        "ursula@gmail.com",
+        "iain@gmail.com",
+        "octavia@gmail.com",
+        "becky@gmail.com",
+    ];
+    let mut csv = "email,name\n".to_owned();
+    for email in emails {
+        csv.push_str(&format!("{},Author\n", email));
+    }
+    import(&app, &csv, "true").await;
+
+    let body = app.get_subscriber_export(&[]).await.text().await.unwrap();
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let exported: Vec<String> = reader
+        .records()
+        .map(|record| record.unwrap()[2].to_owned())
+        .collect();
+    assert_eq!(exported, emails);
+}
+
+#[tokio::test]
+async fn an_empty_export_still_has_a_header() {
+    let app = spawn_app().await;
+    let body = app.get_subscriber_export(&[]).await.text().await.unwrap();
+    assert!(body.starts_with("id,list,email,name,status"));
+    assert_eq!(body.lines().count(), 1);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_exported_as_json_lines_by_status() {
+    let app = spawn_app().await;
+    import(&app, "email,name\nursula@gmail.com,Ursula\n", "true").await;
+    import(&app, "email,name\nterry@gmail.com,Terry\n", "false").await;
+
+    let response = app
+        .get_subscriber_export(&[("format", "ndjson"), ("status", "confirmed")])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let lines: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["email"], "ursula@gmail.com");
+    assert_eq!(lines[0]["list"], "default");
+    assert_eq!(lines[0]["tags"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn invalid_export_parameters_are_rejected() {
+    let app = spawn_app().await;
+    for query in [[("status", "active")], [("format", "xml")]] {
+        let response = app.get_subscriber_export(&query).await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/subscribers/export", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+}
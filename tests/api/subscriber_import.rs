@@ -0,0 +1,283 @@
+use crate::helpers::{spawn_app, spawn_app_with_email_client};
+use lettre::Message;
+use rust_email_newsletter::email_client::{
+    EmailClient, EmailTransport, InMemoryTransport, SendEmailError,
+};
+use std::sync::Arc;
+
+/// Refuses every message to `rejected`, hands the rest to `inner`.
+struct RefusingTransport {
+    rejected: &'static str,
+    inner: InMemoryTransport,
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for RefusingTransport {
+    async fn send(&self, message: Message) -> Result<(), SendEmailError> {
+        if message
+            .envelope()
+            .to()
+            .iter()
+            .any(|to| to.to_string() == self.rejected)
+        {
+            return Err(SendEmailError::Permanent("Mailbox unavailable".into()));
+        }
+        self.inner.send(message).await
+    }
+}
+
+#[tokio::test]
+async fn imported_subscribers_are_pending_until_they_confirm() {
+    let app = spawn_app().await;
+    let csv = "email,name\nursula@gmail.com,Ursula\nle.guin@gmail.com,Le Guin\n";
+
+    let response = app
+        .post_subscriber_import(&[("consent_source", "mailchimp export")], csv)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["accepted"], 2);
+    assert_eq!(report["rejected"], 0);
+
+    let saved = sqlx::query!("SELECT email, status, consent_source FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 2);
+    for subscriber in saved {
+        assert_eq!(subscriber.status, "pending_confirmation");
+        assert_eq!(subscriber.consent_source, "mailchimp export");
+    }
+    let tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.len(), 2);
+
+    app.dispatch_all_confirmation_emails().await;
+    for token in tokens {
+        let confirmation_link =
+            EmailClient::get_confirmation_link(&app.base_url, &token.subscription_token);
+        assert!(app.check_confirmation_mail_exist(confirmation_link));
+    }
+}
+
+#[tokio::test]
+async fn long_files_are_saved_in_chunks() {
+    let app = spawn_app().await;
+    app.post_subscriptions("name=testName&email=subscriber700%40gmail.com")
+        .await;
+    let mut csv = "email,name\n".to_owned();
+    for i in 0..1200 {
+        csv.push_str(&format!("subscriber{}@gmail.com,Subscriber\n", i));
+    }
+
+    let response = app
+        .post_subscriber_import(&[("consent_source", "import")], &csv)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["accepted"], 1199);
+    assert_eq!(report["rejected"], 1);
+    let rows = report["rows"].as_array().unwrap();
+    assert_eq!(rows.len(), 1200);
+    assert_eq!(rows[700]["reason"], "Already subscribed to this list");
+    let queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM confirmation_email_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 1199);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_imported_as_already_confirmed() {
+    let app = spawn_app().await;
+    let response = app
+        .post_subscriber_import(
+            &[
+                ("consent_source", "paper form"),
+                ("skip_confirmation", "true"),
+            ],
+            "email,name\nursula@gmail.com,Ursula\n",
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    let tokens = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.count, 0);
+}
+
+#[tokio::test]
+async fn imported_subscribers_keep_their_locale() {
+    let app = spawn_app().await;
+    let csv = "email,name,locale\nursula@gmail.com,Ursula,fr\nle.guin@gmail.com,Le Guin,xx\n";
+
+    let response = app
+        .post_subscriber_import(&[("consent_source", "import")], csv)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT email, locale FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let locales: Vec<_> = saved
+        .into_iter()
+        .map(|subscriber| (subscriber.email, subscriber.locale))
+        .collect();
+    assert_eq!(
+        locales,
+        vec![
+            ("le.guin@gmail.com".to_owned(), Some("en".to_owned())),
+            ("ursula@gmail.com".to_owned(), Some("fr".to_owned())),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn the_report_explains_every_rejected_row() {
+    let app = spawn_app().await;
+    app.post_subscriptions("name=testName&email=taken%40gmail.com")
+        .await;
+    let csv = "\
+Name,Email
+Ursula,ursula@gmail.com
+Nobody,not-an-email
+{Braces},braces@gmail.com
+Ursula again,ursula@gmail.com
+Taken,taken@gmail.com
+Shouting,TAKEN@gmail.com
+Domain case,ursula@GMAIL.com
+Taken domain case,taken@Gmail.COM
+";
+
+    let response = app
+        .post_subscriber_import(&[("consent_source", "import")], csv)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["accepted"], 2);
+    assert_eq!(report["rejected"], 6);
+    let rows = report["rows"].as_array().unwrap();
+    let outcomes: Vec<(u64, &str)> = rows
+        .iter()
+        .map(|row| {
+            (
+                row["line"].as_u64().unwrap(),
+                row["outcome"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            (2, "accepted"),
+            (3, "rejected"),
+            (4, "rejected"),
+            (5, "rejected"),
+            (6, "rejected"),
+            // Only the domain of an address is case-insensitive.
+            (7, "accepted"),
+            (8, "rejected"),
+            (9, "rejected"),
+        ]
+    );
+    assert_eq!(rows[3]["reason"], "Duplicate of line 2");
+    assert_eq!(rows[4]["reason"], "Already subscribed to this list");
+    assert_eq!(rows[6]["reason"], "Duplicate of line 2");
+    assert_eq!(rows[7]["reason"], "Already subscribed to this list");
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let mut saved: Vec<&str> = saved.iter().map(|row| row.email.as_str()).collect();
+    saved.sort();
+    assert_eq!(
+        saved,
+        vec!["TAKEN@gmail.com", "taken@gmail.com", "ursula@gmail.com"]
+    );
+}
+
+#[tokio::test]
+async fn imports_without_consent_source_or_columns_are_rejected() {
+    let app = spawn_app().await;
+    let response = app
+        .post_subscriber_import(&[], "email,name\nursula@gmail.com,Ursula\n")
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_subscriber_import(&[("consent_source", "import")], "email\nursula@gmail.com\n")
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_subscriber_import(
+            &[("consent_source", "import"), ("list", "no-such-list")],
+            "email,name\nursula@gmail.com,Ursula\n",
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn imports_require_credentials() {
+    let app = spawn_app().await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/import", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_refused_confirmation_does_not_hold_up_the_rest() {
+    let transport = InMemoryTransport::default();
+    let inner = transport.clone();
+    let app = spawn_app_with_email_client(move |configuration| {
+        EmailClient::new(
+            &configuration.email_client.user_name,
+            &configuration.email_client.user_mail,
+            Arc::new(RefusingTransport {
+                rejected: "bounce@gmail.com",
+                inner,
+            }),
+        )
+    })
+    .await;
+    let csv =
+        "email,name\nursula@gmail.com,Ursula\nbounce@gmail.com,Bounce\nle.guin@gmail.com,Le Guin\n";
+
+    let response = app
+        .post_subscriber_import(&[("consent_source", "mailchimp export")], csv)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["accepted"], 3);
+
+    app.dispatch_all_confirmation_emails().await;
+    assert_eq!(transport.messages().len(), 2);
+    // The refused address is left pending, signing up again mails it a fresh link.
+    let queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM confirmation_email_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+    let pending = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM subscriptions WHERE status = 'pending_confirmation'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(pending.count, 3);
+}
@@ -0,0 +1,210 @@
+use chrono::{Duration, Utc};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn insert_subscriber(app: &TestApp, email: &str, status: &str, days_ago: i64) -> Uuid {
+    let subscriber_id = app.insert_subscriber("default", email, "testName", status).await;
+    app.backdate_subscriber(subscriber_id, days_ago).await;
+    subscriber_id
+}
+
+async fn listed_emails(app: &TestApp, query: &[(&str, &str)]) -> Vec<String> {
+    let response = app.get_subscribers(query).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let page: serde_json::Value = response.json().await.unwrap();
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|subscriber| subscriber["email"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[tokio::test]
+async fn requests_without_credentials_are_rejected() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ursula@gmail.com", "confirmed", 0).await;
+    let client = reqwest::Client::new();
+    for request in [
+        client.get(format!("{}/admin/subscribers", app.address)),
+        client.get(format!("{}/admin/subscribers/{}", app.address, subscriber_id)),
+        client
+            .patch(format!("{}/admin/subscribers/{}", app.address, subscriber_id))
+            .json(&serde_json::json!({"name": "Ursula"})),
+        client.delete(format!("{}/admin/subscribers/{}", app.address, subscriber_id)),
+    ] {
+        let response = request.send().await.expect("Failed to execute request.");
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn every_subscriber_is_listed_exactly_once_across_pages() {
+    let app = spawn_app().await;
+    let mut expected = HashSet::new();
+    for i in 0..5 {
+        let email = format!("subscriber{}@gmail.com", i);
+        insert_subscriber(&app, &email, "confirmed", i).await;
+        expected.insert(email);
+    }
+
+    let mut seen = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut query = vec![("limit", "2")];
+        if let Some(cursor) = &cursor {
+            query.push(("cursor", cursor));
+        }
+        let page: serde_json::Value = app.get_subscribers(&query).await.json().await.unwrap();
+        let subscribers = page["subscribers"].as_array().unwrap();
+        assert!(subscribers.len() <= 2);
+        seen.extend(
+            subscribers
+                .iter()
+                .map(|subscriber| subscriber["email"].as_str().unwrap().to_owned()),
+        );
+        match page["next_cursor"].as_str() {
+            Some(next_cursor) => cursor = Some(next_cursor.to_owned()),
+            None => break,
+        }
+    }
+    // Newest first.
+    assert_eq!(seen.first().unwrap(), "subscriber0@gmail.com");
+    assert_eq!(seen.len(), 5);
+    assert_eq!(seen.into_iter().collect::<HashSet<_>>(), expected);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status_email_and_date() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@gmail.com", "confirmed", 1).await;
+    insert_subscriber(&app, "le_guin@example.com", "pending_confirmation", 10).await;
+    insert_subscriber(&app, "leguin@example.com", "unsubscribed", 30).await;
+
+    let emails = listed_emails(&app, &[("status", "confirmed")]).await;
+    assert_eq!(emails, vec!["ursula@gmail.com"]);
+
+    let emails = listed_emails(&app, &[("email", "EXAMPLE")]).await;
+    assert_eq!(emails, vec!["le_guin@example.com", "leguin@example.com"]);
+
+    // `_` is not a wildcard.
+    let emails = listed_emails(&app, &[("email", "le_")]).await;
+    assert_eq!(emails, vec!["le_guin@example.com"]);
+
+    let after = (Utc::now() - Duration::days(20)).to_rfc3339();
+    let before = (Utc::now() - Duration::days(5)).to_rfc3339();
+    let emails = listed_emails(
+        &app,
+        &[("subscribed_after", &after), ("subscribed_before", &before)],
+    )
+    .await;
+    assert_eq!(emails, vec!["le_guin@example.com"]);
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected() {
+    let app = spawn_app().await;
+    for query in [
+        [("status", "active")],
+        [("cursor", "not-a-cursor")],
+        [("limit", "0")],
+        [("limit", "1000")],
+    ] {
+        let response = app.get_subscribers(&query).await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
+
+#[tokio::test]
+async fn a_subscriber_can_be_fetched() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ursula@gmail.com", "confirmed", 0).await;
+
+    let response = app.get_subscriber(subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscriber["email"], "ursula@gmail.com");
+    assert_eq!(subscriber["status"], "confirmed");
+    assert_eq!(subscriber["list"], "default");
+
+    let response = app.get_subscriber(Uuid::new_v4()).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn name_and_status_can_be_edited() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ursula@gmail.com", "confirmed", 0).await;
+
+    let response = app
+        .patch_subscriber(
+            subscriber_id,
+            serde_json::json!({"name": "Ursula", "status": "unsubscribed"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscriber["name"], "Ursula");
+    assert_eq!(subscriber["status"], "unsubscribed");
+}
+
+#[tokio::test]
+async fn edits_outside_the_state_machine_are_rejected() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ursula@gmail.com", "complained", 0).await;
+
+    let response = app
+        .patch_subscriber(
+            subscriber_id,
+            serde_json::json!({"name": "Ursula", "status": "confirmed"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+    // The whole edit is rolled back, not only the status change.
+    let saved = sqlx::query!(
+        "SELECT name, status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.name, "testName");
+    assert_eq!(saved.status, "complained");
+
+    let response = app
+        .patch_subscriber(subscriber_id, serde_json::json!({"name": "<script>"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .patch_subscriber(Uuid::new_v4(), serde_json::json!({"name": "Ursula"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_removes_their_tokens() {
+    let app = spawn_app().await;
+    let response = app
+        .post_subscriptions("name=testName&email=testEmail%40gmail.com")
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    let response = app.delete_subscriber(subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let tokens = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.count, 0);
+    let response = app.delete_subscriber(subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
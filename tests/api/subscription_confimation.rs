@@ -1,6 +1,6 @@
 use rust_email_newsletter::email_client::EmailClient;
 
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, TestApp};
 
 #[tokio::test]
 async fn confirmation_without_token_are_rejected() {
@@ -26,3 +26,86 @@ async fn confirmation_wrong_token_are_rejected() {
         .expect("Failed to execute request");
     assert_eq!(401, response.status().as_u16());
 }
+#[tokio::test]
+async fn expired_tokens_are_rejected_with_410() {
+    let app = spawn_app().await;
+    let subscriber_id = app
+        .insert_subscriber(
+            "default",
+            "testEmail@gmail.com",
+            "testName",
+            "pending_confirmation",
+        )
+        .await;
+    sqlx::query!(
+        "INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at) VALUES ($1, $2, now() - interval '100 days')",
+        "expiredToken",
+        subscriber_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert expired token");
+
+    let confimation_link = EmailClient::get_confirmation_link(&app.address, "expiredToken");
+    let response = reqwest::Client::new()
+        .get(confimation_link.0)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(410, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+async fn insert_subscriber_with_token(app: &TestApp, status: &str, token: &str) {
+    let subscriber_id = app
+        .insert_subscriber("default", "testEmail@gmail.com", "testName", status)
+        .await;
+    sqlx::query!(
+        "INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)",
+        token,
+        subscriber_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert token");
+}
+#[tokio::test]
+async fn clicking_confirmation_link_confirms_a_pending_subscriber() {
+    let app = spawn_app().await;
+    insert_subscriber_with_token(&app, "pending_confirmation", "pendingToken").await;
+    let confimation_link = EmailClient::get_confirmation_link(&app.address, "pendingToken");
+    let response = reqwest::Client::new()
+        .get(confimation_link.0)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "confirmed");
+}
+#[tokio::test]
+async fn unsubscribed_subscribers_cannot_be_confirmed() {
+    let app = spawn_app().await;
+    insert_subscriber_with_token(&app, "unsubscribed", "staleToken").await;
+    let confimation_link = EmailClient::get_confirmation_link(&app.address, "staleToken");
+    let response = reqwest::Client::new()
+        .get(confimation_link.0)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(409, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "unsubscribed");
+}
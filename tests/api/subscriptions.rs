@@ -1,5 +1,6 @@
-use crate::helpers::spawn_app;
-use rust_email_newsletter::email_client::EmailClient;
+use crate::helpers::{spawn_app, spawn_app_with_email_client};
+use rust_email_newsletter::email_client::{EmailClient, InMemoryTransport};
+use std::sync::Arc;
 use test_case::test_case;
 #[tokio::test]
 async fn check_form_data_valid() {
@@ -18,8 +19,11 @@ async fn check_form_data_valid() {
     assert_eq!(saved.name, "testName");
 
     //Check confirrmation mail are send
-    let subscription_token = response.text().await.expect("Failed to get response");
-    let confimation_link = EmailClient::get_confirmation_link("http://127.0.0.1", &subscription_token);
+    let saved = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved token");
+    let confimation_link = EmailClient::get_confirmation_link("http://127.0.0.1", &saved.subscription_token);
     assert!(app.check_confirmation_mail_exist(confimation_link));
 
 }
@@ -52,6 +56,218 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
         .execute(&app.db_pool)
         .await
         .unwrap();
-    let response = app.post_subscriptions(body.into()).await;
+    let response = app.post_subscriptions(body).await;
     assert_eq!(response.status().as_u16(), 500);
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn subscribing_again_while_pending_resends_confirmation() {
+    let app = spawn_app().await;
+    let subscriber_id = app
+        .insert_subscriber(
+            "default",
+            "testEmail@gmail.com",
+            "testName",
+            "pending_confirmation",
+        )
+        .await;
+
+    let response = app
+        .post_subscriptions("name=testName&email=testEmail%40gmail.com")
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT subscription_token, subscriber_id FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved token");
+    assert_eq!(saved.subscriber_id, subscriber_id);
+    let confimation_link =
+        EmailClient::get_confirmation_link("http://127.0.0.1", &saved.subscription_token);
+    assert!(app.check_confirmation_mail_exist(confimation_link));
+}
+
+#[tokio::test]
+async fn subscribing_twice_does_not_fail() {
+    let app = spawn_app().await;
+    let body = "name=testName&email=testEmail%40gmail.com";
+    assert_eq!(200, app.post_subscriptions(body).await.status().as_u16());
+    assert_eq!(200, app.post_subscriptions(body).await.status().as_u16());
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.len(), 1);
+}
+
+#[tokio::test]
+async fn the_same_address_with_a_differently_cased_domain_is_one_subscription() {
+    let app = spawn_app().await;
+    for body in [
+        "name=testName&email=testEmail%40Gmail.COM",
+        "name=testName&email=testEmail%40gmail.com",
+    ] {
+        assert_eq!(200, app.post_subscriptions(body).await.status().as_u16());
+    }
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "testEmail@gmail.com");
+}
+
+#[tokio::test]
+async fn concurrent_signups_for_the_same_address_do_not_fail() {
+    let app = spawn_app().await;
+    let body = "name=testName&email=testEmail%40gmail.com";
+    let (first, second) = tokio::join!(app.post_subscriptions(body), app.post_subscriptions(body));
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.len(), 1);
+}
+
+#[tokio::test]
+async fn new_subscribers_are_pending_confirmation() {
+    let app = spawn_app().await;
+    let response = app
+        .post_subscriptions("name=testName&email=testEmail%40gmail.com")
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_can_subscribe_again() {
+    let app = spawn_app().await;
+    app.insert_subscriber("default", "testEmail@gmail.com", "testName", "unsubscribed").await;
+
+    let response = app
+        .post_subscriptions("name=testName&email=testEmail%40gmail.com")
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn unknown_statuses_are_rejected_by_the_database() {
+    let app = spawn_app().await;
+    let result = sqlx::query!(
+        "INSERT INTO subscriptions (id, list_id, email, name, subscribed_at, status) VALUES ($1, (SELECT list_id FROM lists WHERE slug = 'default'), $2, $3, now(), 'active')",
+        uuid::Uuid::new_v4(),
+        "testEmail@gmail.com",
+        "testName",
+    )
+    .execute(&app.db_pool)
+    .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn confirmation_emails_go_through_the_configured_transport() {
+    let transport = InMemoryTransport::default();
+    let app = spawn_app_with_email_client(|configuration| {
+        EmailClient::new(
+            &configuration.email_client.user_name,
+            &configuration.email_client.user_mail,
+            Arc::new(transport.clone()),
+        )
+    })
+    .await;
+
+    let response = app
+        .post_subscriptions("name=testName&email=inMemory%40gmail.com")
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let messages = transport.messages();
+    assert_eq!(messages.len(), 1);
+    let raw = String::from_utf8(messages[0].formatted()).unwrap();
+    assert!(raw.contains("To: testName <inMemory@gmail.com>"));
+    assert!(!app.check_newsletter_delivered("Kither's newsletter email confirmation", "inMemory@gmail.com"));
+}
+
+#[tokio::test]
+async fn confirmation_email_uses_the_requested_locale() {
+    let app = spawn_app().await;
+    let email = format!("{}@gmail.com", uuid::Uuid::new_v4());
+    let response = app
+        .post_subscriptions(format!(
+            "name=testName&email={}&locale=fr",
+            email.replace('@', "%40")
+        ))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let message = app
+        .get_delivered_newsletter("Confirmez votre abonnement à la newsletter de Kither", &email)
+        .expect("French confirmation email was not sent");
+    assert!(message.html.contains("Bienvenue dans notre newsletter"));
+    assert!(message.text.contains("Bienvenue dans notre newsletter, testName"));
+}
+
+#[tokio::test]
+async fn the_negotiated_locale_is_stored_and_reused_for_later_confirmations() {
+    let app = spawn_app().await;
+    let email = format!("{}@gmail.com", uuid::Uuid::new_v4());
+    let body = format!("name=testName&email={}", email.replace('@', "%40"));
+    app.post_subscriptions(format!("{}&locale=fr", body)).await;
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.locale.as_deref(), Some("fr"));
+
+    // Signing up again from an English browser still gets the French email.
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", "en")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    assert!(!app.check_newsletter_delivered("Kither's newsletter email confirmation", &email));
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.locale.as_deref(), Some("fr"));
+}
+
+#[test_case("fr-CA, en;q=0.5", "Confirmez votre abonnement à la newsletter de Kither"; "accept_language_match")]
+#[test_case("de, ja;q=0.8", "Kither's newsletter email confirmation"; "unsupported_language_falls_back")]
+#[tokio::test]
+async fn confirmation_email_follows_accept_language(accept_language: &str, subject: &str) {
+    let app = spawn_app().await;
+    let email = format!("{}@gmail.com", uuid::Uuid::new_v4());
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", accept_language)
+        .body(format!("name=testName&email={}", email.replace('@', "%40")))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    assert!(app.check_newsletter_delivered(subject, &email));
+}
@@ -0,0 +1,436 @@
+use rust_email_newsletter::domain::{PreferencesToken, UnsubscribeToken};
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn get_statuses(app: &TestApp, email: &str) -> Vec<(String, String)> {
+    sqlx::query!(
+        "SELECT l.slug, s.status FROM subscriptions s JOIN lists l ON l.list_id = s.list_id WHERE s.email = $1 ORDER BY l.created_at",
+        email,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscriptions")
+    .into_iter()
+    .map(|row| (row.slug, row.status))
+    .collect()
+}
+
+#[tokio::test]
+async fn preferences_require_a_preferences_token() {
+    let app = spawn_app().await;
+    let subscriber_id = app.insert_confirmed_subscriber("default", "testEmail@gmail.com").await;
+
+    let response = app
+        .get_preferences(&format!("{}.deadbeef", subscriber_id))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let unsubscribe_token = UnsubscribeToken::generate(subscriber_id, &app.hmac_secret);
+    let response = app.get_preferences(unsubscribe_token.as_ref()).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .put_preferences(
+            unsubscribe_token.as_ref(),
+            serde_json::json!({"name": "Mallory"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn preferences_show_the_subscriber_and_every_list() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let subscriber_id = app.insert_confirmed_subscriber("default", "testEmail@gmail.com").await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+
+    let response = app.get_preferences(token.as_ref()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let preferences: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(preferences["name"], "testName");
+    assert_eq!(preferences["email"], "testEmail@gmail.com");
+    assert_eq!(preferences["digest_frequency"], "immediate");
+    assert_eq!(
+        preferences["lists"],
+        serde_json::json!([
+            {"slug": "default", "name": "Newsletter", "subscribed": true},
+            {"slug": "rust-weekly", "name": "Rust Weekly", "subscribed": false},
+        ])
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name_and_digest_frequency() {
+    let app = spawn_app().await;
+    let subscriber_id = app.insert_confirmed_subscriber("default", "testEmail@gmail.com").await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+
+    let response = app
+        .put_preferences(
+            token.as_ref(),
+            serde_json::json!({"name": "Ursula", "digest_frequency": "weekly"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let preferences: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(preferences["name"], "Ursula");
+    assert_eq!(preferences["digest_frequency"], "weekly");
+
+    let saved = sqlx::query!("SELECT name, digest_frequency FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.name, "Ursula");
+    assert_eq!(saved.digest_frequency, "weekly");
+}
+
+#[tokio::test]
+async fn invalid_preferences_are_rejected() {
+    let app = spawn_app().await;
+    let subscriber_id = app.insert_confirmed_subscriber("default", "testEmail@gmail.com").await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+    let test_cases = vec![
+        (serde_json::json!({"name": "  "}), 400, "blank name"),
+        (
+            serde_json::json!({"name": "<script>"}),
+            400,
+            "forbidden characters",
+        ),
+        (
+            serde_json::json!({"digest_frequency": "hourly"}),
+            400,
+            "unknown frequency",
+        ),
+        (
+            serde_json::json!({"lists": ["Not A Slug"]}),
+            400,
+            "invalid list slug",
+        ),
+        (
+            serde_json::json!({"lists": ["missing"]}),
+            404,
+            "unknown list",
+        ),
+    ];
+    for (body, status, description) in test_cases {
+        let response = app.put_preferences(token.as_ref(), body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "The API did not reject a {}",
+            description
+        );
+    }
+
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.name, "testName");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribers_can_pick_the_lists_they_receive() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+
+    let response = app
+        .put_preferences(
+            token.as_ref(),
+            serde_json::json!({"lists": ["rust-weekly"]}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        get_statuses(&app, &email).await,
+        vec![
+            ("default".to_owned(), "unsubscribed".to_owned()),
+            ("rust-weekly".to_owned(), "confirmed".to_owned()),
+        ]
+    );
+
+    // The same token keeps working, and left lists can be joined again.
+    let response = app
+        .put_preferences(
+            token.as_ref(),
+            serde_json::json!({"lists": ["default", "rust-weekly"]}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let preferences: serde_json::Value = response.json().await.unwrap();
+    assert!(preferences["lists"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|list| list["subscribed"] == true));
+    assert_eq!(
+        get_statuses(&app, &email).await,
+        vec![
+            ("default".to_owned(), "confirmed".to_owned()),
+            ("rust-weekly".to_owned(), "confirmed".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn lists_joined_from_the_preferences_record_where_consent_came_from() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+
+    let response = app
+        .put_preferences(
+            token.as_ref(),
+            serde_json::json!({"lists": ["default", "rust-weekly"]}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(
+        "SELECT s.consent_source FROM subscriptions s JOIN lists l ON l.list_id = s.list_id WHERE s.email = $1 AND l.slug = 'rust-weekly'",
+        email,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.consent_source, "preference_center");
+}
+
+#[tokio::test]
+async fn lists_named_twice_are_joined_once() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+
+    let response = app
+        .put_preferences(
+            token.as_ref(),
+            serde_json::json!({"lists": ["rust-weekly", "rust-weekly"]}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        get_statuses(&app, &email).await,
+        vec![
+            ("default".to_owned(), "unsubscribed".to_owned()),
+            ("rust-weekly".to_owned(), "confirmed".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn rejoining_leaves_bounced_and_complained_subscriptions_alone() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+    app.put_preferences(
+        token.as_ref(),
+        serde_json::json!({"lists": ["default", "rust-weekly"]}),
+    )
+    .await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = CASE WHEN id = $1 THEN 'bounced' ELSE 'complained' END WHERE email = $2",
+        subscriber_id,
+        email,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to update statuses");
+
+    let response = app
+        .put_preferences(
+            token.as_ref(),
+            serde_json::json!({"lists": ["default", "rust-weekly"]}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        get_statuses(&app, &email).await,
+        vec![
+            ("default".to_owned(), "bounced".to_owned()),
+            ("rust-weekly".to_owned(), "complained".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_unsubscribe_from_every_list() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+    app.put_preferences(
+        token.as_ref(),
+        serde_json::json!({"lists": ["default", "rust-weekly"]}),
+    )
+    .await;
+
+    let response = app.post_unsubscribe_from_all(token.as_ref()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        get_statuses(&app, &email).await,
+        vec![
+            ("default".to_owned(), "unsubscribed".to_owned()),
+            ("rust-weekly".to_owned(), "unsubscribed".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn newsletters_carry_a_working_preferences_link() {
+    let app = spawn_app().await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let subject = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let message = app
+        .get_delivered_newsletter(&subject, &email)
+        .expect("Newsletter was not delivered");
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+    assert!(message.html.contains(token.as_ref()));
+    assert!(message.text.contains(token.as_ref()));
+
+    let html = app.get_preferences_html(token.as_ref()).await;
+    assert!(html.contains(r#"<form action="/subscriptions/preferences?token="#));
+}
+
+#[tokio::test]
+async fn the_preferences_page_is_a_form_showing_the_current_preferences() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let subscriber_id = app
+        .insert_subscriber("default", "testEmail@gmail.com", "<b>Ursula</b>", "confirmed")
+        .await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+
+    let html = app.get_preferences_html(token.as_ref()).await;
+    assert!(html.contains(&format!(
+        r#"<form action="/subscriptions/preferences?token={}" method="post">"#,
+        token.as_ref()
+    )));
+    assert!(html.contains(r#"value="&lt;b&gt;Ursula&lt;&#x2f;b&gt;""#));
+    assert!(!html.contains("<b>Ursula</b>"));
+    assert!(html.contains(r#"<option value="immediate" selected>"#));
+    assert!(html.contains(r#"name="lists" value="default" checked>"#));
+    assert!(html.contains(r#"name="lists" value="rust-weekly">"#));
+}
+
+#[tokio::test]
+async fn subscribers_can_save_their_preferences_from_the_form() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+
+    let response = app
+        .post_preferences_form(
+            token.as_ref(),
+            &[
+                ("name", "Ursula"),
+                ("digest_frequency", "weekly"),
+                ("lists", "default"),
+                ("lists", "rust-weekly"),
+            ],
+        )
+        .await;
+    let page = format!("/subscriptions/preferences?token={}", token.as_ref());
+    assert_is_redirect_to(&response, &page);
+    assert_eq!(
+        get_statuses(&app, &email).await,
+        vec![
+            ("default".to_owned(), "confirmed".to_owned()),
+            ("rust-weekly".to_owned(), "confirmed".to_owned()),
+        ]
+    );
+    let html = app.get_preferences_html(token.as_ref()).await;
+    assert!(html.contains("<p class=\"info\"><i>Your preferences have been saved.</i></p>"));
+    assert!(html.contains(r#"value="Ursula""#));
+    assert!(html.contains(r#"<option value="weekly" selected>"#));
+
+    // Unticking every list leaves them all.
+    let response = app
+        .post_preferences_form(token.as_ref(), &[("name", "Ursula")])
+        .await;
+    assert_is_redirect_to(&response, &page);
+    assert_eq!(
+        get_statuses(&app, &email).await,
+        vec![
+            ("default".to_owned(), "unsubscribed".to_owned()),
+            ("rust-weekly".to_owned(), "unsubscribed".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn the_form_reports_invalid_preferences() {
+    let app = spawn_app().await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+
+    let response = app
+        .post_preferences_form(token.as_ref(), &[("name", "  "), ("lists", "default")])
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/subscriptions/preferences?token={}", token.as_ref()),
+    );
+    let html = app.get_preferences_html(token.as_ref()).await;
+    assert!(html.contains("<p class=\"error\">"));
+    assert_eq!(
+        get_statuses(&app, &email).await,
+        vec![("default".to_owned(), "confirmed".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn the_form_can_unsubscribe_from_every_list() {
+    let app = spawn_app().await;
+    app.create_list("rust-weekly").await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    app.insert_confirmed_subscriber("rust-weekly", &email).await;
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let token = PreferencesToken::generate(subscriber_id, &app.hmac_secret);
+
+    // The whole form is submitted, ticked lists included.
+    let response = app
+        .post_preferences_form(
+            token.as_ref(),
+            &[
+                ("name", "testName"),
+                ("lists", "default"),
+                ("lists", "rust-weekly"),
+                ("unsubscribe_all", "true"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        get_statuses(&app, &email).await,
+        vec![
+            ("default".to_owned(), "unsubscribed".to_owned()),
+            ("rust-weekly".to_owned(), "unsubscribed".to_owned()),
+        ]
+    );
+}
@@ -0,0 +1,160 @@
+use base64::engine::general_purpose;
+use base64::Engine;
+use rust_email_newsletter::domain::UnsubscribeToken;
+use rust_email_newsletter::email_client::EmailClient;
+use uuid::Uuid;
+
+use crate::helpers::spawn_app;
+use crate::smtp_sever::MailMessage;
+
+#[tokio::test]
+async fn unsubscribe_without_token_are_rejected() {
+    let app = spawn_app().await;
+    let response = reqwest::Client::new()
+        .get(format!("{}/subscriptions/unsubscribe", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(400, response.status().as_u16());
+}
+#[tokio::test]
+async fn unsubscribe_with_forged_token_are_rejected() {
+    let app = spawn_app().await;
+    let subscriber_id = app.insert_confirmed_subscriber("default", "testEmail@gmail.com").await;
+    let response = app
+        .get_unsubscribe(&format!("{}.deadbeef", subscriber_id))
+        .await;
+    assert_eq!(401, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "confirmed");
+}
+#[tokio::test]
+async fn unsubscribe_link_asks_for_confirmation_without_unsubscribing() {
+    let app = spawn_app().await;
+    let subscriber_id = app.insert_confirmed_subscriber("default", "testEmail@gmail.com").await;
+    let token = UnsubscribeToken::generate(subscriber_id, &app.hmac_secret);
+    let response = app.get_unsubscribe(token.as_ref()).await;
+    assert_eq!(200, response.status().as_u16());
+    let html = response.text().await.unwrap();
+    assert!(html.contains(&format!(
+        r#"<form action="/subscriptions/unsubscribe?token={}" method="post">"#,
+        token.as_ref()
+    )));
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "confirmed");
+}
+#[tokio::test]
+async fn unsubscribe_with_signed_token_unsubscribes() {
+    let app = spawn_app().await;
+    let subscriber_id = app.insert_confirmed_subscriber("default", "testEmail@gmail.com").await;
+    let token = UnsubscribeToken::generate(subscriber_id, &app.hmac_secret);
+    let response = app.post_unsubscribe(token.as_ref()).await;
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "unsubscribed");
+}
+#[tokio::test]
+async fn newsletters_carry_a_working_unsubscribe_link() {
+    let app = spawn_app().await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let subject = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": subject,
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let message = app
+        .get_delivered_newsletter(&subject, &email)
+        .expect("Newsletter was not delivered");
+    let token = UnsubscribeToken::generate(subscriber_id, &app.hmac_secret);
+    assert!(message.html.contains(token.as_ref()));
+
+    app.post_unsubscribe(token.as_ref()).await;
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "subject": Uuid::new_v4().to_string(),
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let tasks = sqlx::query!("SELECT subscriber_id FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch delivery tasks");
+    assert_eq!(tasks.len(), 1);
+}
+fn get_raw_message(message: &MailMessage) -> String {
+    let raw = general_purpose::STANDARD
+        .decode(&message.raw)
+        .expect("Failed to decode raw message");
+    String::from_utf8(raw).expect("Raw message is not valid UTF8")
+}
+#[tokio::test]
+async fn newsletters_carry_one_click_unsubscribe_headers() {
+    let app = spawn_app().await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let subscriber_id = app.insert_confirmed_subscriber("default", &email).await;
+    let subject = Uuid::new_v4().to_string();
+    app.post_newsletter(serde_json::json!({
+        "subject": subject,
+        "content": "<p>Newsletter body as HTML</p>",
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let message = app
+        .get_delivered_newsletter(&subject, &email)
+        .expect("Newsletter was not delivered");
+    // Unfold long header lines before looking at them.
+    let raw = get_raw_message(&message).replace("\r\n ", " ");
+    let token = UnsubscribeToken::generate(subscriber_id, &app.hmac_secret);
+    let unsubscribe_link = EmailClient::get_unsubscribe_link(&app.base_url, token.as_ref());
+    assert!(raw.contains(&format!("List-Unsubscribe: <{}>, <mailto:", unsubscribe_link.0)));
+    assert!(raw.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+
+    // What a mailbox provider sends when the user clicks "Unsubscribe".
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/unsubscribe", app.address))
+        .query(&[("token", token.as_ref())])
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "unsubscribed");
+}
+#[tokio::test]
+async fn confirmation_emails_omit_list_unsubscribe_headers() {
+    let app = spawn_app().await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let body = format!("name=testName&email={}", email.replace('@', "%40"));
+    let response = app.post_subscriptions(body).await;
+    assert_eq!(200, response.status().as_u16());
+
+    let message = app
+        .get_delivered_newsletter("Kither's newsletter email confirmation", &email)
+        .expect("Confirmation email was not delivered");
+    assert!(!get_raw_message(&message).contains("List-Unsubscribe"));
+}
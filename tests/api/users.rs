@@ -0,0 +1,246 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+struct CreatedUser {
+    user_id: String,
+    username: String,
+    password: String,
+}
+
+async fn create_user(app: &TestApp, is_superuser: bool) -> CreatedUser {
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+    let response = app
+        .post_users(serde_json::json!({
+            "username": &username,
+            "password": &password,
+            "is_superuser": is_superuser,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    CreatedUser {
+        user_id: body["user_id"].as_str().unwrap().to_owned(),
+        username,
+        password,
+    }
+}
+
+/// Any admin endpoint behind Basic auth will do.
+async fn get_lists_as(app: &TestApp, user: &CreatedUser) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/admin/lists", &app.address))
+        .basic_auth(&user.username, Some(&user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Logs `user` in on a cookie jar of its own, next to the test user's.
+async fn login_as(app: &TestApp, user: &CreatedUser) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &user.username,
+            "password": &user.password,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    let app = spawn_app().await;
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/users", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_created_user_can_authenticate() {
+    let app = spawn_app().await;
+    let user = create_user(&app, false).await;
+
+    let response = get_lists_as(&app, &user).await;
+    assert_eq!(response.status().as_u16(), 200);
+    login_as(&app, &user).await;
+
+    let users: serde_json::Value = app.get_users().await.json().await.unwrap();
+    let listed = users
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|u| u["user_id"] == user.user_id.as_str())
+        .expect("The new user is not listed");
+    assert_eq!(listed["is_superuser"], false);
+    assert!(listed["disabled_at"].is_null());
+}
+
+#[tokio::test]
+async fn only_superusers_can_manage_users() {
+    let app = spawn_app().await;
+    let user = create_user(&app, false).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/users", &app.address))
+        .basic_auth(&user.username, Some(&user.password))
+        .json(&serde_json::json!({
+            "username": Uuid::new_v4().to_string(),
+            "password": Uuid::new_v4().to_string(),
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn create_user_returns_a_400_for_invalid_data() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"username": " ", "password": Uuid::new_v4().to_string()}),
+            "blank username",
+        ),
+        (
+            serde_json::json!({"username": "with:colon", "password": Uuid::new_v4().to_string()}),
+            "username with a colon",
+        ),
+        (
+            serde_json::json!({"username": Uuid::new_v4().to_string(), "password": "short1"}),
+            "short password",
+        ),
+        (
+            serde_json::json!({"username": Uuid::new_v4().to_string(), "password": "onlylettersinhere"}),
+            "letters only password",
+        ),
+    ];
+    for (body, description) in test_cases {
+        let response = app.post_users(body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request for a {}",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn usernames_are_unique() {
+    let app = spawn_app().await;
+    let response = app
+        .post_users(serde_json::json!({
+            "username": &app.test_user.username,
+            "password": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn passwords_are_stored_hashed() {
+    let app = spawn_app().await;
+    let user = create_user(&app, false).await;
+
+    let saved = sqlx::query!(
+        "SELECT password_hash FROM users WHERE username = $1",
+        user.username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved user");
+    assert!(saved.password_hash.starts_with("$argon2id$"));
+    assert!(!saved.password_hash.contains(&user.password));
+}
+
+#[tokio::test]
+async fn a_disabled_user_is_logged_out_and_cannot_authenticate() {
+    let app = spawn_app().await;
+    let user = create_user(&app, false).await;
+    let client = login_as(&app, &user).await;
+
+    let response = app
+        .patch_user(&user.user_id, serde_json::json!({"disabled": true}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(!body["disabled_at"].is_null());
+
+    let response = get_lists_as(&app, &user).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_re_enabled_user_can_authenticate_again() {
+    let app = spawn_app().await;
+    let user = create_user(&app, false).await;
+    app.patch_user(&user.user_id, serde_json::json!({"disabled": true}))
+        .await;
+
+    let response = app
+        .patch_user(&user.user_id, serde_json::json!({"disabled": false}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["disabled_at"].is_null());
+
+    let response = get_lists_as(&app, &user).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_deleted_user_cannot_authenticate() {
+    let app = spawn_app().await;
+    let user = create_user(&app, true).await;
+
+    let response = app.delete_user(&user.user_id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = get_lists_as(&app, &user).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.delete_user(&user.user_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn superusers_cannot_disable_or_delete_themselves() {
+    let app = spawn_app().await;
+    let user_id = app.test_user.user_id.to_string();
+
+    let response = app
+        .patch_user(&user_id, serde_json::json!({"disabled": true}))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.delete_user(&user_id).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn editing_an_unknown_user_returns_a_404() {
+    let app = spawn_app().await;
+    let response = app
+        .patch_user(
+            &Uuid::new_v4().to_string(),
+            serde_json::json!({"disabled": true}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}